@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+/// Name of the config file looked up in the working directory and in the
+/// user's config directory.
+pub const CONFIG_FILE: &str = "hush.toml";
+
+/// Defaults read from `hush.toml`. Flags given on the command line take
+/// precedence over these.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Model path or name used when `--model` is not given.
+    pub model: Option<PathBuf>,
+    /// Language used when `--language` is not given.
+    pub language: Option<String>,
+    /// Thread count used when `--threads` is not given.
+    pub threads: Option<i32>,
+}
+
+impl Config {
+    /// Loads the first config file found, or the empty config if there is
+    /// none.
+    pub fn load() -> Result<Config, anyhow::Error> {
+        match config_paths().into_iter().find(|path| path.is_file()) {
+            Some(path) => Config::read(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Config, anyhow::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+        toml::from_str(&contents).map_err(|err| anyhow!("invalid {}: {}", path.display(), err))
+    }
+}
+
+/// Places a config file is looked for, in order of precedence: the working
+/// directory, then `$XDG_CONFIG_HOME/hush` or `~/.config/hush`.
+pub fn config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+    if let Some(config_dir) = config_dir {
+        paths.push(config_dir.join("hush").join(CONFIG_FILE));
+    }
+    paths
+}
@@ -0,0 +1,362 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use anyhow::anyhow;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::utils::resample_iter;
+
+/// File extensions of the audio formats hush can read.
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "wav", "flac", "mp3", "m4a", "mp4", "aac", "ogg", "oga", "mka", "mkv", "webm",
+];
+
+/// Whether `path` has the extension of a format hush can read.
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Interleaved PCM decoded from an audio file.
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Decodes the first audio track of a compressed file (MP3, M4A/AAC, FLAC,
+/// Ogg Vorbis, ...) into interleaved `f32` samples.
+pub fn decode_audio(path: &Path) -> Result<DecodedAudio, anyhow::Error> {
+    let mut samples = Vec::new();
+    let (sample_rate, channels) = decode_blocks(path, |block, _| {
+        samples.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(DecodedAudio {
+        samples,
+        sample_rate,
+        channels,
+    })
+}
+
+/// Mono audio produced by [`decode_audio_mono`], along with the layout it was
+/// decoded from.
+pub struct MonoAudio {
+    pub samples: Vec<f32>,
+    pub source_rate: u32,
+    pub source_channels: u16,
+}
+
+/// Decodes a compressed file like [`decode_audio`], but downmixes it to mono
+/// and resamples it to `sample_rate` without holding the decoded audio in
+/// memory. The mono audio at its original rate is staged in a temporary file
+/// in `tmp_dir`, which is removed again whether or not decoding succeeds.
+/// Decoding stops with an error once `abort` is set.
+pub fn decode_audio_mono(
+    path: &Path,
+    sample_rate: u32,
+    tmp_dir: &Path,
+    abort: Option<&AtomicBool>,
+) -> Result<MonoAudio, anyhow::Error> {
+    let (staged, file) = TempFile::create(tmp_dir)?;
+    let mut writer = BufWriter::new(file);
+    let mut frames = 0;
+    let (source_rate, source_channels) = decode_blocks(path, |block, channels| {
+        if abort.is_some_and(|abort| abort.load(Ordering::SeqCst)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "decoding interrupted",
+            ));
+        }
+        for frame in block.chunks(channels.max(1)) {
+            let sample = frame.iter().sum::<f32>() / frame.len() as f32;
+            writer.write_all(&sample.to_le_bytes())?;
+            frames += 1;
+        }
+        Ok(())
+    })
+    .map_err(|err| {
+        anyhow!(
+            "{:#} (staging decoded audio in {})",
+            err,
+            staged.path().display()
+        )
+    })?;
+
+    let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+    let mut error = None;
+    let staged_samples = std::iter::from_fn(|| {
+        let mut bytes = [0; 4];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => Some(f32::from_le_bytes(bytes)),
+            Err(err) => {
+                error = Some(err);
+                None
+            }
+        }
+    });
+    let samples = resample_iter(staged_samples, frames, source_rate, sample_rate);
+    if let Some(err) = error {
+        anyhow::bail!("failed to read back {}: {}", staged.path().display(), err);
+    }
+
+    Ok(MonoAudio {
+        samples,
+        source_rate,
+        source_channels,
+    })
+}
+
+/// A temporary file that is deleted when dropped.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates an empty file with a name unique to this process in `dir`.
+    pub fn create(dir: &Path) -> Result<(TempFile, File), anyhow::Error> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "hush-{}-{}.pcm",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| {
+                anyhow!(
+                    "failed to create temporary file {}: {}",
+                    path.display(),
+                    err
+                )
+            })?;
+        Ok((TempFile { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Decodes the first audio track of `path`, handing each block of interleaved
+/// samples to `block` along with its channel count. Returns the sample rate
+/// and channel count of the track.
+fn decode_blocks<F>(path: &Path, mut block: F) -> Result<(u32, u16), anyhow::Error>
+where
+    F: FnMut(&[f32], usize) -> io::Result<()>,
+{
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow!("no audio track found in {}", path.display()))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let mut channels = track
+        .codec_params
+        .channels
+        .map(|channels| channels.count() as u16)
+        .unwrap_or(0);
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupt packets are skipped rather than aborting the whole file.
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count() as u16;
+
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        block(buffer.samples(), channels as usize)?;
+    }
+
+    Ok((sample_rate, channels))
+}
+
+/// Sample encodings accepted for raw PCM input.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Signed 16-bit little-endian integers.
+    S16le,
+    /// 32-bit little-endian floats.
+    F32le,
+}
+
+impl PcmFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmFormat::S16le => 2,
+            PcmFormat::F32le => 4,
+        }
+    }
+
+    /// Converts whole samples from `bytes` to `f32`, appending them to
+    /// `samples`. Trailing bytes that don't form a whole sample are ignored.
+    pub fn convert(self, bytes: &[u8], samples: &mut Vec<f32>) {
+        let chunks = bytes.chunks_exact(self.bytes_per_sample());
+        match self {
+            PcmFormat::S16le => samples.extend(
+                chunks.map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0),
+            ),
+            PcmFormat::F32le => samples.extend(
+                chunks.map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            ),
+        }
+    }
+}
+
+/// Layout of headerless PCM audio, which carries no description of itself.
+#[derive(Clone, Copy, Debug)]
+pub struct PcmSpec {
+    pub format: PcmFormat,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Reads interleaved raw PCM until EOF.
+pub fn decode_pcm<R: Read>(mut reader: R, spec: PcmSpec) -> Result<DecodedAudio, anyhow::Error> {
+    if spec.sample_rate == 0 || spec.channels == 0 {
+        anyhow::bail!("raw PCM needs a non-zero sample rate and channel count");
+    }
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut samples = Vec::with_capacity(bytes.len() / spec.format.bytes_per_sample());
+    spec.format.convert(&bytes, &mut samples);
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::FlacWriter;
+    use crate::utils::{downmix, resample};
+
+    /// An empty directory of its own for a test, removed again afterwards.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hush-test-{}-{}", process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn decode_audio_mono_matches_in_memory_conversion_and_cleans_up() {
+        let dir = scratch_dir("decode-mono");
+        let input = dir.join("input.flac");
+        let mut writer = FlacWriter::create(&input, 2, 44100, 16).unwrap();
+        for i in 0..10_000 {
+            writer.write_sample((i % 200) * 100).unwrap();
+            writer.write_sample(-(i % 50) * 300).unwrap();
+        }
+        writer.finalize().unwrap();
+        let staging = dir.join("staging");
+        fs::create_dir(&staging).unwrap();
+
+        let audio = decode_audio(&input).unwrap();
+        let expected = resample(&downmix(&audio.samples, 2), 44100, 16000);
+        let mono = decode_audio_mono(&input, 16000, &staging, None).unwrap();
+
+        assert_eq!((mono.source_rate, mono.source_channels), (44100, 2));
+        assert_eq!(mono.samples, expected);
+        assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn decode_audio_mono_cleans_up_after_an_error() {
+        let dir = scratch_dir("decode-error");
+        let input = dir.join("input.mp3");
+        fs::write(&input, b"not audio").unwrap();
+
+        assert!(decode_audio_mono(&input, 16000, &dir, None).is_err());
+        let left: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(left.len(), 1);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn decode_audio_mono_stops_when_aborted() {
+        let dir = scratch_dir("decode-abort");
+        let input = dir.join("input.flac");
+        let mut writer = FlacWriter::create(&input, 1, 16000, 16).unwrap();
+        for i in 0..10_000 {
+            writer.write_sample(i % 100).unwrap();
+        }
+        writer.finalize().unwrap();
+        let staging = dir.join("staging");
+        fs::create_dir(&staging).unwrap();
+
+        let abort = AtomicBool::new(true);
+        let result = decode_audio_mono(&input, 16000, &staging, Some(&abort));
+        assert!(result.is_err_and(|err| err.to_string().contains("interrupted")));
+        assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
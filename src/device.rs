@@ -0,0 +1,278 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::HostId;
+
+/// Finds an available host whose name matches `name`, ignoring case.
+pub fn find_host(name: &str) -> Option<HostId> {
+    cpal::available_hosts()
+        .into_iter()
+        .find(|host_id| host_id.name().eq_ignore_ascii_case(name))
+}
+
+/// Lists the input devices available on the given host.
+pub fn list_input_devices(host_id: HostId) -> Result<Vec<cpal::Device>, anyhow::Error> {
+    let host = cpal::host_from_id(host_id)
+        .map_err(|err| anyhow!("host {} is unavailable: {}", host_id.name(), err))?;
+    let devices = host.input_devices().map_err(|err| {
+        anyhow!(
+            "failed to enumerate input devices for host {}: {}",
+            host_id.name(),
+            err
+        )
+    })?;
+    Ok(devices.collect())
+}
+
+/// Sample rates checked against a device's supported ranges when describing it.
+const COMMON_SAMPLE_RATES: &[u32] = &[
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// An input device and the configurations it can capture with.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub default_config: Option<cpal::SupportedStreamConfig>,
+    pub configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl DeviceInfo {
+    /// Common sample rates that at least one supported configuration covers.
+    pub fn sample_rates(&self) -> Vec<u32> {
+        COMMON_SAMPLE_RATES
+            .iter()
+            .copied()
+            .filter(|&rate| {
+                self.configs.iter().any(|range| {
+                    range.min_sample_rate().0 <= rate && rate <= range.max_sample_rate().0
+                })
+            })
+            .collect()
+    }
+
+    /// Supported sample formats, without duplicates.
+    pub fn sample_formats(&self) -> Vec<cpal::SampleFormat> {
+        let mut formats: Vec<cpal::SampleFormat> = Vec::new();
+        for range in &self.configs {
+            if !formats.contains(&range.sample_format()) {
+                formats.push(range.sample_format());
+            }
+        }
+        formats
+    }
+
+    /// Lowest and highest supported channel counts.
+    pub fn channel_range(&self) -> Option<(u16, u16)> {
+        let channels = self.configs.iter().map(|range| range.channels());
+        Some((channels.clone().min()?, channels.max()?))
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    /// Formats as e.g. `Yeti — 16000/44100/48000 Hz, F32/I16, 1–2 ch`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.configs.is_empty() {
+            return write!(f, " — no supported input configurations");
+        }
+
+        let rates: Vec<String> = self.sample_rates().iter().map(u32::to_string).collect();
+        let formats: Vec<String> = self
+            .sample_formats()
+            .iter()
+            .map(|format| format!("{:?}", format))
+            .collect();
+        write!(f, " — {} Hz, {}", rates.join("/"), formats.join("/"))?;
+        match self.channel_range() {
+            Some((min, max)) if min == max => write!(f, ", {} ch", min),
+            Some((min, max)) => write!(f, ", {}–{} ch", min, max),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Describes every input device on the given host along with the
+/// configurations it supports.
+pub fn describe_input_devices(host_id: HostId) -> Result<Vec<DeviceInfo>, anyhow::Error> {
+    Ok(list_input_devices(host_id)?
+        .iter()
+        .map(describe_input_device)
+        .collect())
+}
+
+/// Describes a single input device. Devices that fail to report their
+/// configurations are listed without any.
+pub fn describe_input_device(device: &cpal::Device) -> DeviceInfo {
+    DeviceInfo {
+        name: device.name().unwrap_or_else(|_| "<unknown>".to_string()),
+        default_config: device.default_input_config().ok(),
+        configs: device
+            .supported_input_configs()
+            .map(|configs| configs.collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns the input device at `device_index` on the given host, or the
+/// host's default input device when no index is provided. Uses the default
+/// host when no host is provided.
+pub fn get_input_device(
+    device_index: Option<usize>,
+    host_id: Option<HostId>,
+) -> Result<cpal::Device, anyhow::Error> {
+    let host_id = host_id.unwrap_or_else(|| cpal::default_host().id());
+
+    match device_index {
+        Some(device_index) => {
+            let devices = list_input_devices(host_id)?;
+            let count = devices.len();
+            devices.into_iter().nth(device_index).ok_or_else(|| {
+                anyhow!(
+                    "device index {} out of range; {} devices available",
+                    device_index,
+                    count
+                )
+            })
+        }
+        None => cpal::host_from_id(host_id)
+            .map_err(|err| anyhow!("host {} is unavailable: {}", host_id.name(), err))?
+            .default_input_device()
+            .ok_or_else(|| anyhow!("no default input device for host {}", host_id.name())),
+    }
+}
+
+/// Returns the default input device of the default host.
+pub fn default_input() -> Result<cpal::Device, anyhow::Error> {
+    get_input_device(None, None)
+}
+
+/// Returns the default output device of the default host.
+pub fn default_output() -> Result<cpal::Device, anyhow::Error> {
+    cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| anyhow!("no default output device"))
+}
+
+/// Returns the first input device whose name contains `name`, ignoring case.
+/// Uses the default host when no host is provided.
+pub fn get_input_device_by_name(
+    name: &str,
+    host_id: Option<HostId>,
+) -> Result<cpal::Device, anyhow::Error> {
+    let host_id = host_id.unwrap_or_else(|| cpal::default_host().id());
+    let devices = list_input_devices(host_id)?;
+    let needle = name.to_lowercase();
+
+    let names: Vec<String> = devices
+        .iter()
+        .map(|device| device.name().unwrap_or_default())
+        .collect();
+    match names
+        .iter()
+        .position(|device_name| device_name.to_lowercase().contains(&needle))
+    {
+        Some(index) => Ok(devices.into_iter().nth(index).unwrap()),
+        None => Err(anyhow!(
+            "no input device matching {:?}; available devices: {}",
+            name,
+            names.join(", ")
+        )),
+    }
+}
+
+/// Resolves the host chosen on the command line, falling back to the default
+/// host with a warning when `host` doesn't name an available one.
+pub fn resolve_host(host: Option<&str>) -> HostId {
+    match host {
+        Some(host) => find_host(host).unwrap_or_else(|| {
+            log::warn!("no audio host named {:?}, using the default host", host);
+            cpal::default_host().id()
+        }),
+        None => cpal::default_host().id(),
+    }
+}
+
+/// Resolves the input device chosen on the command line. `host` names one of
+/// `cpal::available_hosts()` and falls back to the default host with a
+/// warning when it doesn't match; `name` takes precedence over `index`.
+pub fn select_input_device(
+    host: Option<&str>,
+    index: Option<usize>,
+    name: Option<&str>,
+) -> Result<cpal::Device, anyhow::Error> {
+    let host_id = resolve_host(host);
+    match name {
+        Some(name) => get_input_device_by_name(name, Some(host_id)),
+        None => get_input_device(index, Some(host_id)),
+    }
+}
+
+/// Checks that `device` can capture with the channel count, sample rate and
+/// sample format of `config`.
+pub fn check_input_config(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+) -> Result<(), anyhow::Error> {
+    let ranges: Vec<cpal::SupportedStreamConfigRange> = device.supported_input_configs()?.collect();
+
+    let supported = ranges.iter().any(|range| {
+        range.channels() == config.channels()
+            && range.sample_format() == config.sample_format()
+            && range.min_sample_rate() <= config.sample_rate()
+            && config.sample_rate() <= range.max_sample_rate()
+    });
+    if supported {
+        return Ok(());
+    }
+
+    let available: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "{} ch {}-{} Hz {}",
+                range.channels(),
+                range.min_sample_rate().0,
+                range.max_sample_rate().0,
+                range.sample_format()
+            )
+        })
+        .collect();
+    Err(anyhow!(
+        "device does not support {} channel(s) at {} Hz {}; supported configurations: {}",
+        config.channels(),
+        config.sample_rate().0,
+        config.sample_format(),
+        available.join(", ")
+    ))
+}
+
+/// Picks the supported input configuration closest to `desired`, preferring a
+/// matching sample format, then the nearest sample rate, then the nearest
+/// channel count.
+pub fn pick_config(
+    device: &cpal::Device,
+    desired: &cpal::SupportedStreamConfig,
+) -> Result<cpal::SupportedStreamConfig, anyhow::Error> {
+    let rate = desired.sample_rate();
+
+    let range = device
+        .supported_input_configs()?
+        .min_by_key(|range| {
+            let rate_distance = if rate < range.min_sample_rate() {
+                range.min_sample_rate().0 - rate.0
+            } else {
+                rate.0.saturating_sub(range.max_sample_rate().0)
+            };
+            (
+                range.sample_format() != desired.sample_format(),
+                rate_distance,
+                range.channels().abs_diff(desired.channels()),
+            )
+        })
+        .ok_or_else(|| anyhow!("device has no supported input configurations"))?;
+
+    let rate = rate.clamp(range.min_sample_rate(), range.max_sample_rate());
+    Ok(range.with_sample_rate(rate))
+}
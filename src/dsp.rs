@@ -0,0 +1,173 @@
+use std::f32::consts::{PI, SQRT_2};
+use std::ops::Range;
+
+use crate::utils::rms;
+
+/// Applies a second-order Butterworth high-pass filter in place, removing
+/// content below `cutoff_hz` such as HVAC or handling rumble.
+pub fn highpass(samples: &mut [f32], sample_rate: u32, cutoff_hz: f32) {
+    // Coefficients from the RBJ audio EQ cookbook with Q = 1/sqrt(2).
+    let omega = 2.0 * PI * cutoff_hz / sample_rate as f32;
+    let alpha = omega.sin() / 2.0 * SQRT_2;
+    let cos = omega.cos();
+
+    let a0 = 1.0 + alpha;
+    let b0 = (1.0 + cos) / 2.0 / a0;
+    let b1 = -(1.0 + cos) / a0;
+    let b2 = b0;
+    let a1 = -2.0 * cos / a0;
+    let a2 = (1.0 - alpha) / a0;
+
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    for sample in samples.iter_mut() {
+        let x0 = *sample;
+        let y0 = b0 * x0 + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        x2 = x1;
+        x1 = x0;
+        y2 = y1;
+        y1 = y0;
+        *sample = y0;
+    }
+}
+
+/// Peak level below which audio is treated as silence and left unscaled.
+const SILENCE_PEAK: f32 = 1e-4;
+
+/// Scales `samples` in place so that their peak reaches `target_peak`.
+/// Returns the gain applied, or `None` when the audio is effectively silent
+/// and amplifying it would only raise the noise floor.
+pub fn normalize(samples: &mut [f32], target_peak: f32) -> Option<f32> {
+    let peak = samples
+        .iter()
+        .map(|sample| sample.abs())
+        .fold(0.0, f32::max);
+    if peak < SILENCE_PEAK {
+        return None;
+    }
+
+    let gain = target_peak / peak;
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+    Some(gain)
+}
+
+/// Length of the blocks whose level decides what counts as silence.
+const TRIM_FRAME_SECONDS: f32 = 0.02;
+
+/// Shortest run of leading or trailing silence worth dropping; anything
+/// shorter is likely a deliberate pause and is kept.
+const TRIM_MIN_GAP_SECONDS: f32 = 1.0;
+
+/// Silence kept next to the audio so soft onsets and decays aren't cut.
+const TRIM_PADDING_SECONDS: f32 = 0.25;
+
+/// Finds the part of `samples` left after dropping leading and trailing
+/// blocks whose RMS level is below `threshold`. Returns an empty range when
+/// the whole input is silent.
+pub fn trim_silence(samples: &[f32], sample_rate: u32, threshold: f32) -> Range<usize> {
+    let frame = ((sample_rate as f32 * TRIM_FRAME_SECONDS) as usize).max(1);
+    let loud = |(_, block): &(usize, &[f32])| rms(block) >= threshold;
+    let Some((first, _)) = samples.chunks(frame).enumerate().find(loud) else {
+        return 0..0;
+    };
+    let (last, _) = samples
+        .chunks(frame)
+        .enumerate()
+        .rfind(loud)
+        .expect("a loud block exists");
+
+    let min_gap = (sample_rate as f32 * TRIM_MIN_GAP_SECONDS) as usize;
+    let padding = (sample_rate as f32 * TRIM_PADDING_SECONDS) as usize;
+    let speech_start = first * frame;
+    let speech_end = ((last + 1) * frame).min(samples.len());
+    let start = if speech_start >= min_gap {
+        speech_start - padding
+    } else {
+        0
+    };
+    let end = if samples.len() - speech_end >= min_gap {
+        speech_end + padding
+    } else {
+        samples.len()
+    };
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|n| (2.0 * PI * frequency * n as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// RMS of the second half, once the filter has settled.
+    fn settled_rms(samples: &[f32]) -> f32 {
+        rms(&samples[samples.len() / 2..])
+    }
+
+    #[test]
+    fn highpass_removes_dc_and_low_frequencies() {
+        let mut dc = vec![0.5; RATE as usize];
+        highpass(&mut dc, RATE, 100.0);
+        assert!(settled_rms(&dc) < 1e-3);
+
+        let mut rumble = sine(20.0, 1.0);
+        let before = settled_rms(&rumble);
+        highpass(&mut rumble, RATE, 100.0);
+        assert!(settled_rms(&rumble) < before * 0.1);
+    }
+
+    #[test]
+    fn highpass_passes_frequencies_above_the_cutoff() {
+        let mut tone = sine(1000.0, 1.0);
+        let before = settled_rms(&tone);
+        highpass(&mut tone, RATE, 100.0);
+        assert!((settled_rms(&tone) / before - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn normalize_scales_the_peak_to_the_target() {
+        let mut samples = vec![0.1, -0.25, 0.05];
+        assert_eq!(normalize(&mut samples, 0.5), Some(2.0));
+        assert_eq!(samples, [0.2, -0.5, 0.1]);
+    }
+
+    #[test]
+    fn normalize_leaves_silence_untouched() {
+        let mut samples = vec![0.0; 100];
+        assert_eq!(normalize(&mut samples, 0.5), None);
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn trim_silence_drops_long_leading_and_trailing_silence() {
+        let mut samples = vec![0.0; 2 * RATE as usize];
+        samples.extend(sine(440.0, 1.0));
+        samples.extend(vec![0.0; 3 * RATE as usize]);
+        // The tone starts at 2 s and ends at 3 s; 0.25 s of padding is kept.
+        assert_eq!(
+            trim_silence(&samples, RATE, 0.01),
+            (RATE as usize * 7 / 4)..(RATE as usize * 13 / 4)
+        );
+    }
+
+    #[test]
+    fn trim_silence_keeps_short_gaps() {
+        let mut samples = vec![0.0; RATE as usize / 2];
+        samples.extend(sine(440.0, 1.0));
+        samples.extend(vec![0.0; RATE as usize / 2]);
+        assert_eq!(trim_silence(&samples, RATE, 0.01), 0..samples.len());
+    }
+
+    #[test]
+    fn trim_silence_returns_nothing_for_silent_input() {
+        assert!(trim_silence(&vec![0.0; RATE as usize], RATE, 0.01).is_empty());
+        assert!(trim_silence(&[], RATE, 0.01).is_empty());
+    }
+}
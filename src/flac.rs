@@ -0,0 +1,443 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Number of samples per channel in every frame but the last.
+const BLOCK_SIZE: usize = 4096;
+
+/// Byte offset of the STREAMINFO block body: "fLaC" plus the block header.
+const STREAMINFO_OFFSET: u64 = 8;
+
+/// Minimal lossless FLAC encoder for integer PCM. Each channel of a frame is
+/// stored with a second-order fixed predictor and Rice-coded residuals, or
+/// verbatim when that turns out smaller. The STREAMINFO block is rewritten with
+/// the final sample count by [`FlacWriter::finalize`].
+pub struct FlacWriter<W: Write + Seek> {
+    inner: W,
+    channels: u16,
+    bits_per_sample: u16,
+    sample_rate: u32,
+    /// Interleaved samples of the frame being collected.
+    block: Vec<i32>,
+    frame_number: u64,
+    total_samples: u64,
+}
+
+impl FlacWriter<BufWriter<File>> {
+    pub fn create(
+        path: &Path,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> io::Result<Self> {
+        FlacWriter::new(
+            BufWriter::new(File::create(path)?),
+            channels,
+            sample_rate,
+            bits_per_sample,
+        )
+    }
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    pub fn new(
+        inner: W,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(invalid_input("FLAC supports 1 to 8 channels"));
+        }
+        if ![8, 16, 24].contains(&bits_per_sample) {
+            return Err(invalid_input(
+                "FLAC output supports 8, 16 or 24 bits per sample",
+            ));
+        }
+        if sample_rate == 0 || sample_rate >= 1 << 20 {
+            return Err(invalid_input("sample rate out of range for FLAC"));
+        }
+
+        let mut writer = FlacWriter {
+            inner,
+            channels,
+            bits_per_sample,
+            sample_rate,
+            block: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_samples: 0,
+        };
+        writer.inner.write_all(b"fLaC")?;
+        // Last metadata block, type 0 (STREAMINFO), 34 bytes long.
+        writer.inner.write_all(&[0x80, 0, 0, 34])?;
+        writer.write_streaminfo()?;
+        Ok(writer)
+    }
+
+    /// Number of interleaved channels each frame holds.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Appends one sample. Samples are interleaved across channels, as in
+    /// `hound::WavWriter::write_sample`.
+    pub fn write_sample(&mut self, sample: i32) -> io::Result<()> {
+        self.block.push(sample);
+        if self.block.len() == BLOCK_SIZE * self.channels as usize {
+            self.write_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered samples and fills in the total sample count.
+    pub fn finalize(mut self) -> io::Result<()> {
+        // A trailing partial frame is dropped so every frame holds whole
+        // inter-channel samples.
+        let whole = self.block.len() - self.block.len() % self.channels as usize;
+        self.block.truncate(whole);
+        if !self.block.is_empty() {
+            self.write_frame()?;
+        }
+
+        self.inner.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+        self.write_streaminfo()?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()
+    }
+
+    fn write_streaminfo(&mut self) -> io::Result<()> {
+        let mut bits = BitWriter::default();
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        // Minimum and maximum frame sizes are unknown.
+        bits.write(0, 24);
+        bits.write(0, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(self.bits_per_sample as u64 - 1, 5);
+        bits.write(self.total_samples, 36);
+        // An all-zero MD5 signature means the signature was not computed.
+        for _ in 0..16 {
+            bits.write(0, 8);
+        }
+        self.inner.write_all(&bits.into_bytes())
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let channels = self.channels as usize;
+        let block_size = self.block.len() / channels;
+
+        let mut bits = BitWriter::default();
+        bits.write(0b11_1111_1111_1110, 14);
+        bits.write(0, 1);
+        // Fixed block size; frames are numbered rather than sample-addressed.
+        bits.write(0, 1);
+        if block_size == BLOCK_SIZE {
+            bits.write(0b1100, 4);
+        } else {
+            // Block size stored as a 16-bit value at the end of the header.
+            bits.write(0b0111, 4);
+        }
+        // Sample rate taken from STREAMINFO.
+        bits.write(0, 4);
+        // Independent channels.
+        bits.write(channels as u64 - 1, 4);
+        let size_code = match self.bits_per_sample {
+            8 => 0b001,
+            16 => 0b100,
+            _ => 0b110,
+        };
+        bits.write(size_code, 3);
+        bits.write(0, 1);
+        write_utf8_number(&mut bits, self.frame_number);
+        if block_size != BLOCK_SIZE {
+            bits.write(block_size as u64 - 1, 16);
+        }
+        let crc = crc8(bits.bytes());
+        bits.write(crc as u64, 8);
+
+        for channel in 0..channels {
+            let samples: Vec<i32> = self
+                .block
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect();
+            write_subframe(&mut bits, &samples, self.bits_per_sample as u32);
+        }
+        bits.align();
+        let crc = crc16(bits.bytes());
+        bits.write(crc as u64, 16);
+
+        self.inner.write_all(&bits.into_bytes())?;
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Writes a subframe using whichever of the verbatim and second-order fixed
+/// encodings is smaller.
+fn write_subframe(bits: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+
+    if samples.len() > 2 {
+        let residuals: Vec<i64> = samples
+            .windows(3)
+            .map(|window| window[2] as i64 - 2 * window[1] as i64 + window[0] as i64)
+            .collect();
+        let (parameter, residual_bits) = rice_parameter(&residuals);
+        // Warm-up samples, coding method, partition order and parameter.
+        let fixed_bits = 2 * bits_per_sample as u64 + 2 + 4 + 4 + residual_bits;
+        if fixed_bits < verbatim_bits {
+            // Zero padding bit, SUBFRAME_FIXED of order 2, no wasted bits.
+            bits.write(0, 1);
+            bits.write(0b001010, 6);
+            bits.write(0, 1);
+            for &sample in &samples[..2] {
+                bits.write_signed(sample as i64, bits_per_sample);
+            }
+            // Rice coding with 4-bit parameters and a single partition.
+            bits.write(0, 2);
+            bits.write(0, 4);
+            bits.write(parameter as u64, 4);
+            for &residual in &residuals {
+                bits.write_rice(residual, parameter);
+            }
+            return;
+        }
+    }
+
+    // Zero padding bit, SUBFRAME_VERBATIM, no wasted bits.
+    bits.write(0, 1);
+    bits.write(0b000001, 6);
+    bits.write(0, 1);
+    for &sample in samples {
+        bits.write_signed(sample as i64, bits_per_sample);
+    }
+}
+
+/// Picks the Rice parameter that codes `residuals` in the fewest bits,
+/// returning it along with that size.
+fn rice_parameter(residuals: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = residuals.iter().map(|&residual| zigzag(residual)).collect();
+    (0..15)
+        .map(|parameter| {
+            let size: u64 = folded
+                .iter()
+                .map(|&value| (value >> parameter) + 1 + parameter as u64)
+                .sum();
+            (parameter, size)
+        })
+        .min_by_key(|&(_, size)| size)
+        .unwrap()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Frame and sample numbers use the same variable-length coding as UTF-8,
+/// extended to 36 bits.
+fn write_utf8_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+    let mut continuation = 1;
+    while value >= 1 << (5 * continuation + 6) {
+        continuation += 1;
+    }
+    // The lead byte starts with one 1 bit per byte in the sequence.
+    let marker = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+    bits.write(marker | (value >> (6 * continuation)), 8);
+    for index in (0..continuation).rev() {
+        bits.write(0x80 | ((value >> (6 * index)) & 0x3F), 8);
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Accumulates a big-endian bit stream.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    filled: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `count` bits of `value`, most significant first.
+    fn write(&mut self, value: u64, count: u32) {
+        for shift in (0..count).rev() {
+            self.current = (self.current << 1) | ((value >> shift) & 1) as u8;
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, count: u32) {
+        self.write(value as u64 & ((1 << count) - 1), count);
+    }
+
+    fn write_rice(&mut self, value: i64, parameter: u32) {
+        let folded = zigzag(value);
+        let quotient = folded >> parameter;
+        for _ in 0..quotient {
+            self.write(0, 1);
+        }
+        self.write(1, 1);
+        self.write(folded, parameter);
+    }
+
+    /// Pads the stream with zero bits up to the next byte boundary.
+    fn align(&mut self) {
+        if self.filled > 0 {
+            self.write(0, 8 - self.filled);
+        }
+    }
+
+    /// Whole bytes written so far.
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode_audio;
+    use std::path::PathBuf;
+
+    /// Deterministic test signal: a ramp with pseudo-random noise on top, plus
+    /// the occasional full-scale sample so some subframes end up verbatim.
+    fn signal(len: usize, bits_per_sample: u16) -> Vec<i32> {
+        let max = (1i64 << (bits_per_sample - 1)) - 1;
+        let min = -(1i64 << (bits_per_sample - 1));
+        let mut state: u32 = 12345;
+        (0..len)
+            .map(|index| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = (state >> 16) as i64 % 512 - 256;
+                let ramp = (index as i64 % 2000 - 1000) * (max / 1000);
+                let sample = match index % 9973 {
+                    0 => max,
+                    1 => min,
+                    _ => ramp + noise,
+                };
+                sample.clamp(min, max) as i32
+            })
+            .collect()
+    }
+
+    /// Encodes `samples_per_channel` samples of every channel, decodes the file
+    /// again with symphonia and checks that every sample survived unchanged.
+    fn round_trip(name: &str, channels: u16, bits_per_sample: u16, samples_per_channel: usize) {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("hush-test-{}-{}.flac", std::process::id(), name));
+        let samples = signal(samples_per_channel * channels as usize, bits_per_sample);
+        let mut writer = FlacWriter::create(&path, channels, 44100, bits_per_sample).unwrap();
+        for &sample in &samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let decoded = decode_audio(&path);
+        std::fs::remove_file(&path).ok();
+        let decoded = decoded.unwrap();
+        assert_eq!((decoded.sample_rate, decoded.channels), (44100, channels));
+        let scale = (1i64 << (bits_per_sample - 1)) as f32;
+        let expected: Vec<f32> = samples
+            .iter()
+            .map(|&sample| sample as f32 / scale)
+            .collect();
+        assert_eq!(decoded.samples.len(), expected.len());
+        let mismatch = decoded
+            .samples
+            .iter()
+            .zip(&expected)
+            .position(|(decoded, expected)| decoded != expected);
+        assert_eq!(mismatch, None);
+    }
+
+    #[test]
+    fn round_trips_mono_16_bit_past_128_frames() {
+        // Frame numbers from 128 on take two bytes, and the last frame is short.
+        round_trip("mono-16", 1, 16, 130 * BLOCK_SIZE + 1000);
+    }
+
+    #[test]
+    fn round_trips_stereo_16_bit() {
+        round_trip("stereo-16", 2, 16, 3 * BLOCK_SIZE + 17);
+    }
+
+    #[test]
+    fn round_trips_mono_24_bit() {
+        round_trip("mono-24", 1, 24, 2 * BLOCK_SIZE + 5);
+    }
+
+    #[test]
+    fn round_trips_stereo_24_bit() {
+        round_trip("stereo-24", 2, 24, 4 * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn frame_numbers_are_utf8_coded() {
+        for value in [0, 0x7F, 0x80, 0x7FF, 0x800, 0xFFFF, 0x10000, 0x10FFFF] {
+            let mut bits = BitWriter::default();
+            write_utf8_number(&mut bits, value);
+            let mut utf8 = [0; 4];
+            let expected = char::from_u32(value as u32).unwrap().encode_utf8(&mut utf8);
+            assert_eq!(bits.into_bytes(), expected.as_bytes(), "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn crcs_match_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// Output formats supported by the Transcribe command.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// All segments joined into one paragraph, wrapped at `--width`.
+    TextParagraph,
+    Srt,
+    Vtt,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    /// File extension used for transcripts in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text | OutputFormat::TextParagraph => "txt",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A transcribed segment with timestamps relative to the start of the input.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Segment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Average probability of the segment's tokens.
+    pub confidence: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+}
+
+/// Timing of a single token within a segment.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Word {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+/// Formats a millisecond offset as an SRT timestamp (`HH:MM:SS,mmm`).
+pub fn srt_timestamp(ms: i64) -> String {
+    let (hours, minutes, seconds, millis) = split_ms(ms);
+    format!("{:02}:{:02}:{:02},{:03}", hours, minutes, seconds, millis)
+}
+
+/// Formats a millisecond offset as a `HH:MM:SS` clock time.
+pub fn clock_timestamp(ms: i64) -> String {
+    let (hours, minutes, seconds, _) = split_ms(ms);
+    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+}
+
+/// Formats a millisecond offset as a WebVTT timestamp (`HH:MM:SS.mmm`).
+pub fn vtt_timestamp(ms: i64) -> String {
+    let (hours, minutes, seconds, millis) = split_ms(ms);
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
+fn split_ms(ms: i64) -> (i64, i64, i64, i64) {
+    let ms = ms.max(0);
+    (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// Writes `segments` in the given format. `timestamps` only affects plain
+/// text and `width` only affects paragraph output.
+pub fn write_segments<W: Write>(
+    output: &mut W,
+    segments: &[Segment],
+    format: OutputFormat,
+    timestamps: bool,
+    width: usize,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => write_text(output, segments, timestamps),
+        OutputFormat::TextParagraph => write_paragraph(output, segments, width),
+        OutputFormat::Srt => write_srt(output, segments),
+        OutputFormat::Vtt => write_vtt(output, segments),
+        OutputFormat::Json => write_json(output, segments),
+        OutputFormat::Csv => write_csv(output, segments),
+    }
+}
+
+/// Writes one segment per line, prefixed with its start time when
+/// `timestamps` is set.
+pub fn write_text<W: Write>(
+    output: &mut W,
+    segments: &[Segment],
+    timestamps: bool,
+) -> io::Result<()> {
+    for segment in segments {
+        if timestamps {
+            writeln!(
+                output,
+                "[{}] {}",
+                clock_timestamp(segment.start_ms),
+                segment.text.trim()
+            )?;
+        } else {
+            writeln!(output, "{}", segment.text)?;
+        }
+    }
+    Ok(())
+}
+
+/// Joins the text of all segments into a single paragraph with collapsed
+/// whitespace, wrapped at `width` columns. A width of zero disables wrapping.
+pub fn write_paragraph<W: Write>(
+    output: &mut W,
+    segments: &[Segment],
+    width: usize,
+) -> io::Result<()> {
+    let mut line = String::new();
+    for word in segments
+        .iter()
+        .flat_map(|segment| segment.text.split_whitespace())
+    {
+        if width > 0 && !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width
+        {
+            writeln!(output, "{}", line)?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        writeln!(output, "{}", line)?;
+    }
+    Ok(())
+}
+
+pub fn write_srt<W: Write>(output: &mut W, segments: &[Segment]) -> io::Result<()> {
+    for (index, segment) in segments.iter().enumerate() {
+        writeln!(output, "{}", index + 1)?;
+        writeln!(
+            output,
+            "{} --> {}",
+            srt_timestamp(segment.start_ms),
+            srt_timestamp(segment.end_ms)
+        )?;
+        writeln!(output, "{}", segment.text.trim())?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+pub fn write_vtt<W: Write>(output: &mut W, segments: &[Segment]) -> io::Result<()> {
+    writeln!(output, "WEBVTT")?;
+    writeln!(output)?;
+    for segment in segments {
+        writeln!(
+            output,
+            "{} --> {}",
+            vtt_timestamp(segment.start_ms),
+            vtt_timestamp(segment.end_ms)
+        )?;
+        writeln!(output, "{}", segment.text.trim())?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(output: &mut W, segments: &[Segment]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *output, segments)?;
+    writeln!(output)
+}
+
+/// Writes a `start_ms,end_ms,text` header followed by one row per segment.
+pub fn write_csv<W: Write>(output: &mut W, segments: &[Segment]) -> io::Result<()> {
+    writeln!(output, "start_ms,end_ms,text")?;
+    for segment in segments {
+        writeln!(
+            output,
+            "{},{},{}",
+            segment.start_ms,
+            segment.end_ms,
+            csv_field(segment.text.trim())
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote or line break,
+/// doubling any quotes inside it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+        Segment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn vtt_timestamp_formats_hours_minutes_seconds_and_millis() {
+        assert_eq!(vtt_timestamp(0), "00:00:00.000");
+        assert_eq!(vtt_timestamp(3_723_456), "01:02:03.456");
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_delimiters_quotes_and_line_breaks() {
+        let segments = [
+            segment(0, 1000, " plain text"),
+            segment(1000, 2000, " yes, no"),
+            segment(2000, 3000, " she said \"hi\""),
+            segment(3000, 4000, " two\nlines"),
+        ];
+        let mut output = Vec::new();
+        write_csv(&mut output, &segments).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "start_ms,end_ms,text\n\
+             0,1000,plain text\n\
+             1000,2000,\"yes, no\"\n\
+             2000,3000,\"she said \"\"hi\"\"\"\n\
+             3000,4000,\"two\nlines\"\n"
+        );
+    }
+
+    #[test]
+    fn paragraph_joins_segments_and_wraps_at_width() {
+        let segments = [
+            segment(0, 1000, " The quick  brown"),
+            segment(1000, 2000, "fox jumps\nover the lazy dog."),
+        ];
+        let mut output = Vec::new();
+        write_paragraph(&mut output, &segments, 20).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "The quick brown fox\njumps over the lazy\ndog.\n"
+        );
+
+        let mut output = Vec::new();
+        write_paragraph(&mut output, &segments, 0).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "The quick brown fox jumps over the lazy dog.\n"
+        );
+    }
+
+    #[test]
+    fn paragraph_keeps_words_longer_than_the_width_whole() {
+        let segments = [segment(0, 1000, "a supercalifragilistic word")];
+        let mut output = Vec::new();
+        write_paragraph(&mut output, &segments, 5).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a\nsupercalifragilistic\nword\n"
+        );
+    }
+}
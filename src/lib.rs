@@ -1,3 +1,9 @@
-pub mod inference;
+pub mod config;
+pub mod decode;
 pub mod device;
+pub mod dsp;
+pub mod flac;
+pub mod format;
+pub mod models;
+pub mod transcribe;
 pub mod utils;
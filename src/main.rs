@@ -1,17 +1,162 @@
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use clap::{Parser, Subcommand}; use cpal::traits::{DeviceTrait, StreamTrait};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use clap::{Args, Parser, Subcommand}; use cpal::traits::{DeviceTrait, StreamTrait};
+use log::info;
+use whisper_rs::WhisperContext;
 
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
+use hush::config::{Config, CONFIG_FILE};
+use hush::format::{OutputFormat, Segment, write_segments};
+use hush::transcribe::{DEFAULT_LOAD_TIMEOUT, TranscribeOptions, load_model, silence_whisper_logging, transcribe_file, transcribe_file_timed, transcribe_files_timed, transcribe_pcm_timed};
+use hush::decode::{PcmFormat, PcmSpec, decode_audio, is_audio_file};
+use hush::models::{MODELS, cached_model_path, download_model, read_model_header, resolve_model};
+use hush::device::{check_input_config, default_output, describe_input_device, describe_input_devices, pick_config, resolve_host, select_input_device};
+use hush::flac::FlacWriter;
+use hush::utils::{AudioFormat, AudioWriter, downmix, expand_path_template, parse_duration, Buffer, Resampler, LevelMeter, SilenceMonitor, dbfs, DEFAULT_SILENCE_THRESHOLD, initialize_write_stream, initialize_buffered_stream, initialize_monitor_stream, default_threads};
 
-use hush::device::{get_input_device, list_input_devices};
-use hush::utils::{Buffer, initialize_write_stream, initialize_buffered_stream};
+/// Exit code of `transcribe` when the input contained no speech, so scripts
+/// can tell an empty transcript apart from a failure (exit code 1).
+const NO_SPEECH_EXIT_CODE: u8 = 3;
+
+/// Share of clipped samples above which `record` warns about the input gain.
+const CLIPPING_WARN_FRACTION: f64 = 0.001;
+
+/// How long `record --dry-run` captures for to check that audio arrives.
+const DRY_RUN_DURATION: std::time::Duration = std::time::Duration::from_millis(500);
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None,
+          after_help = "The model can also be given with HUSH_MODEL. Defaults for --model, --language and \
+                        --threads can be set in hush.toml in the current directory or in ~/.config/hush/.\n\n\
+                        `transcribe` exits with code 3 when a single input has audio but no speech.")]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Print more detail; -v also shows whisper.cpp's own log output and
+    /// -vv adds trace output.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Model and decoding options shared by every subcommand that runs Whisper.
+#[derive(Args, Debug)]
+struct DecodeArgs {
+    /// Path to a GGML model, or the name of one fetched with `models --download`.
+    #[arg(short = 'm', long = "model", env = "HUSH_MODEL")]
+    model: Option<PathBuf>,
+
+    /// Spoken language, or `auto` to detect it. Defaults to `auto`.
+    #[arg(short = 'l', long = "language")]
+    language: Option<String>,
+
+    /// Translate the speech to English. Whisper can only translate into
+    /// English; --language still sets the source language.
+    #[arg(short = 't', long = "translate")]
+    translate: bool,
+
+    /// Use beam search with the given beam size instead of greedy decoding.
+    #[arg(short = 'b', long = "beam-size")]
+    beam_size: Option<i32>,
+
+    /// Decode this many candidates with greedy sampling and keep the
+    /// best. Slower, but can help with difficult audio.
+    #[arg(long = "best-of", value_name = "N", default_value_t = 1, conflicts_with = "beam_size",
+          value_parser = clap::value_parser!(i32).range(1..))]
+    best_of: i32,
+
+    /// Initial prompt used to bias spelling of names and domain vocabulary.
+    #[arg(short = 'p', long = "prompt")]
+    prompt: Option<String>,
+
+    /// Sampling temperature. Zero decodes deterministically.
+    #[arg(long = "temperature", default_value_t = 0.0)]
+    temperature: f32,
+
+    /// Step the temperature is raised by when a decoding attempt looks
+    /// like a repetition loop or low-confidence output. Zero disables
+    /// the fallback.
+    #[arg(long = "temperature-inc", default_value_t = 0.2)]
+    temperature_inc: f32,
+
+    /// Don't feed previously decoded text back in as context. Helps with
+    /// disjoint clips where Whisper would otherwise invent continuity or
+    /// get stuck repeating itself.
+    #[arg(long = "no-context")]
+    no_context: bool,
+
+    /// Force a single segment per window. Useful for short utterances
+    /// such as voice commands.
+    #[arg(long = "single-segment")]
+    single_segment: bool,
+
+    /// Remove bracketed annotations such as `[Music]` or `(laughs)` from
+    /// the transcript.
+    #[arg(long = "strip-annotations")]
+    strip_annotations: bool,
+
+    /// Number of threads to run Whisper on. Defaults to one per core.
+    #[arg(long = "threads")]
+    threads: Option<i32>,
+
+    /// Offload inference to the GPU. Requires whisper-rs to be built with
+    /// CUDA or Metal support.
+    #[arg(long = "gpu")]
+    gpu: bool,
+
+    /// Give up loading the model after this long, e.g. 90, 45s or 5m.
+    /// Bare numbers are seconds.
+    #[arg(long = "load-timeout", value_name = "DURATION", value_parser = parse_timeout, default_value_t = DEFAULT_LOAD_TIMEOUT.as_secs())]
+    load_timeout: u64,
+}
+
+impl DecodeArgs {
+    /// Resolves the model given by --model or the config file.
+    fn model_path(&self) -> Result<PathBuf, anyhow::Error> {
+        match &self.model {
+            Some(model) => resolve_model(model),
+            None => anyhow::bail!("no model given; pass --model, set HUSH_MODEL or set `model` in {}", CONFIG_FILE),
+        }
+    }
+
+    fn load_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.load_timeout)
+    }
+}
+
+/// Parses --load-timeout. A zero timeout would give up before the loader
+/// thread got a chance to start, so it is rejected.
+fn parse_timeout(value: &str) -> Result<u64, String> {
+    match parse_duration(value)? {
+        0 => Err("the timeout must be longer than zero".to_string()),
+        seconds => Ok(seconds),
+    }
+}
+
+impl From<&DecodeArgs> for TranscribeOptions {
+    fn from(args: &DecodeArgs) -> Self {
+        TranscribeOptions {
+            language: args.language.clone().unwrap_or_else(|| "auto".to_string()),
+            translate: args.translate,
+            beam_size: args.beam_size,
+            best_of: args.best_of,
+            prompt: args.prompt.clone(),
+            temperature: args.temperature,
+            temperature_inc: args.temperature_inc,
+            no_context: args.no_context,
+            single_segment: args.single_segment,
+            strip_annotations: args.strip_annotations,
+            threads: args.threads.unwrap_or_else(default_threads),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -19,48 +164,521 @@ enum Commands {
     Device {
         #[arg(short, long)]
         list: bool,
+
+        /// Print every configuration the selected device, or the default
+        /// device, can capture with.
+        #[arg(long = "formats", alias = "list-formats")]
+        formats: bool,
+
+        #[arg(short = 'i', long)]
+        device_index: Option<usize>,
+
+        /// Select the first input device whose name contains this text.
+        #[arg(short = 'n', long, conflicts_with = "device_index")]
+        device_name: Option<String>,
+
+        /// Audio host to use, as listed by `host --list`.
+        #[arg(long)]
+        host: Option<String>,
     },
     Host {
         #[arg(short, long)]
         list: bool,
     },
     Record {
-        #[arg(short = 'd', long = "duration")]
+        /// How long to record, e.g. 90, 45s, 1m30s or 2h. Bare numbers are seconds.
+        #[arg(short = 'd', long = "duration", value_parser = parse_duration)]
         duration: u64,
 
         #[arg(short = 'i', long)]
         device_index: Option<usize>,
 
+        /// Select the first input device whose name contains this text.
+        #[arg(short = 'n', long, conflicts_with = "device_index")]
+        device_name: Option<String>,
+
+        /// Audio host to capture from, as listed by `host --list`.
+        #[arg(long)]
+        host: Option<String>,
+
+        /// File to record to. strftime-style placeholders are replaced with
+        /// the local time, e.g. `rec-%Y%m%d-%H%M%S.wav`.
         #[arg(short, long, value_name = "OUTPUT_FILE")]
         output_file: PathBuf,
+
+        /// Container to write. FLAC is lossless and stores 16-bit samples.
+        #[arg(long = "format", value_enum, default_value_t = AudioFormat::Wav)]
+        format: AudioFormat,
+
+        /// Add to the end of OUTPUT_FILE if it already exists. The existing
+        /// file must have the same channels, sample rate and sample format.
+        #[arg(short = 'a', long = "append")]
+        append: bool,
+
+        /// Overwrite OUTPUT_FILE if it already exists.
+        #[arg(short = 'f', long = "force", conflicts_with = "append")]
+        force: bool,
+
+        /// Stop early once this many seconds of silence follow some speech.
+        /// --duration still caps the length of the recording.
+        #[arg(short = 's', long = "stop-on-silence", value_name = "SECONDS")]
+        stop_on_silence: Option<f64>,
+
+        /// Sample rate to record at. When neither this nor --channels is
+        /// given, the closest configuration to 16 kHz mono is negotiated.
+        #[arg(short = 'r', long = "sample-rate")]
+        sample_rate: Option<u32>,
+
+        #[arg(short = 'c', long = "channels")]
+        channels: Option<u16>,
+
+        /// Show a live input level meter while recording.
+        #[arg(long = "meter")]
+        meter: bool,
+
+        /// Play the input back through the default output device while
+        /// recording. Use headphones; speakers will feed back into the mic.
+        #[arg(long = "monitor")]
+        monitor: bool,
+
+        /// Open the device and capture for a moment to check that audio
+        /// arrives, then report the config and level without writing a file.
+        #[arg(long = "dry-run")]
+        dry_run: bool,
     },
     Transcribe {
-        #[arg(short = 'm', long = "model")]
-        model: PathBuf,
+        #[command(flatten)]
+        decode: DecodeArgs,
 
-        #[arg(short = 'i', long, value_name = "INPUT_FILE")]
-        input_file: PathBuf,
+        /// Audio file to transcribe, or `-` to read raw PCM from stdin as
+        /// described by --pcm-format, --sample-rate and --channels. Given a
+        /// directory, every audio file in it is transcribed and each
+        /// transcript is written next to its source file. Given several
+        /// times, the files are joined in order and transcribed as one
+        /// recording, e.g. a talk recorded in parts.
+        #[arg(short = 'i', long = "input-file", value_name = "INPUT_FILE", required = true)]
+        input_files: Vec<PathBuf>,
+
+        /// File to write the transcript to instead of stdout. When several
+        /// formats are requested, one file per format is written with this
+        /// path's extension replaced, e.g. `talk.srt` and `talk.vtt`.
+        #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+        output_file: Option<PathBuf>,
+
+        /// Directory to write transcripts to, named after each input file
+        /// with the extension of --format. Created if missing.
+        #[arg(long = "output-dir", value_name = "DIR", conflicts_with = "output_file")]
+        output_dir: Option<PathBuf>,
+
+        /// Overwrite transcripts that already exist when transcribing a
+        /// directory or writing to --output-dir.
+        #[arg(long = "force")]
+        force: bool,
+
+        /// Sample encoding of raw PCM read from stdin.
+        #[arg(long = "pcm-format", value_enum, default_value_t = PcmFormat::S16le)]
+        pcm_format: PcmFormat,
+
+        /// Sample rate of raw PCM read from stdin.
+        #[arg(long = "sample-rate", default_value_t = 16000)]
+        sample_rate: u32,
+
+        /// Channel count of raw PCM read from stdin.
+        #[arg(long = "channels", default_value_t = 1)]
+        channels: u16,
+
+        /// Transcript format. Repeat it or separate formats with commas, e.g.
+        /// `-f text,srt,vtt,json`, to write several from one transcription.
+        #[arg(short = 'f', long = "format", value_enum, value_delimiter = ',', default_values_t = [OutputFormat::Text])]
+        format: Vec<OutputFormat>,
+
+        /// Suppress blank output and non-speech tokens, which cuts down on
+        /// hallucinated text over music or noise.
+        #[arg(long = "suppress-non-speech")]
+        suppress_non_speech: bool,
+
+        /// Length in seconds of each chunk passed to Whisper.
+        #[arg(long = "chunk-seconds", default_value_t = 10.0)]
+        chunk_seconds: f64,
 
+        /// Seconds of audio shared between consecutive chunks.
+        #[arg(long = "overlap-seconds", default_value_t = 1.0)]
+        overlap_seconds: f64,
+
+        /// Column to wrap text-paragraph output at. Zero disables wrapping.
+        #[arg(long = "width", default_value_t = 80)]
+        width: usize,
+
+        /// Don't prefix plain text output with segment start times.
+        #[arg(long = "no-timestamps")]
+        no_timestamps: bool,
+
+        /// Include per-token timings as a `words` array in JSON output.
+        #[arg(long = "word-timestamps")]
+        word_timestamps: bool,
+
+        /// Drop segments whose average token probability is below this value.
+        #[arg(long = "min-confidence")]
+        min_confidence: Option<f32>,
+
+        /// Split segments longer than this many characters at word
+        /// boundaries, keeping subtitle cues readable.
+        #[arg(long = "max-segment-length", value_name = "N")]
+        max_segment_length: Option<usize>,
+
+        /// End segments on word boundaries rather than inside a word, also
+        /// where Whisper cuts them to --max-segment-length.
+        #[arg(long = "split-on-word")]
+        split_on_word: bool,
+
+        /// Filter out rumble below this frequency before transcribing, e.g. 80.
+        #[arg(long = "highpass", value_name = "HZ")]
+        highpass: Option<f32>,
+
+        /// Boost quiet recordings so their peak level is close to full scale.
+        #[arg(long = "normalize")]
+        normalize: bool,
+
+        /// Skip long stretches of silence at the start and end of the input.
+        #[arg(long = "trim-silence")]
+        trim_silence: bool,
+
+        /// Only transcribe from this many seconds into the input.
+        #[arg(long = "start", value_name = "SECONDS")]
+        start: Option<f64>,
+
+        /// Stop transcribing this many seconds into the input.
+        #[arg(long = "end", value_name = "SECONDS")]
+        end: Option<f64>,
+
+        /// Add this many seconds to every timestamp, e.g. to line up the
+        /// transcript of a clip with the recording it was cut from.
+        #[arg(long = "time-offset", value_name = "SECONDS", default_value_t = 0.0)]
+        time_offset: f64,
+
+        /// Number of chunks to transcribe in parallel. Each job runs its own
+        /// Whisper state with --threads threads. With more than one job,
+        /// chunks are decoded without the text before them as context, as
+        /// with --no-context.
+        #[arg(short = 'j', long = "jobs", default_value_t = 1)]
+        jobs: usize,
+
+        /// Directory compressed input is decoded into while it is prepared
+        /// for Whisper. Defaults to the system's temporary directory.
+        #[arg(long = "tmp-dir", value_name = "DIR")]
+        tmp_dir: Option<PathBuf>,
     },
     Live {
         #[arg(short = 'i', long)]
         device_index: Option<usize>,
 
+        /// Select the first input device whose name contains this text.
+        #[arg(short = 'n', long, conflicts_with = "device_index")]
+        device_name: Option<String>,
+
+        /// Audio host to capture from, as listed by `host --list`.
+        #[arg(long)]
+        host: Option<String>,
+
+        #[command(flatten)]
+        decode: DecodeArgs,
+
+        /// Let Whisper emit blank output and non-speech tokens, which are
+        /// suppressed by default while transcribing live.
+        #[arg(long = "keep-non-speech")]
+        keep_non_speech: bool,
+
+        /// Split segments longer than this many characters, keeping lines
+        /// readable.
+        #[arg(long = "max-segment-length", value_name = "N")]
+        max_segment_length: Option<usize>,
+
+        /// End segments on word boundaries rather than inside a word.
+        #[arg(long = "split-on-word")]
+        split_on_word: bool,
+
+        /// Length in seconds of the audio window transcribed at a time.
+        #[arg(short = 'w', long = "window-seconds", default_value_t = 3.0)]
+        window_seconds: f64,
+
+        /// RMS level above which audio counts as speech. Zero transcribes
+        /// every window regardless of level.
+        #[arg(long = "vad-threshold", default_value_t = 0.01)]
+        vad_threshold: f32,
+
+        /// Seconds of silence after speech before the window is transcribed.
+        #[arg(long = "silence-seconds", default_value_t = 0.8)]
+        silence_seconds: f64,
+
+        /// Also append each transcribed segment to this file as it arrives.
+        #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+        output_file: Option<PathBuf>,
+
+        /// Prefix lines written to --output with the local time.
+        #[arg(long = "timestamps")]
+        timestamps: bool,
+
+        /// Print each segment as a JSON object on its own line instead of
+        /// plain text.
+        #[arg(long = "json")]
+        json: bool,
+
+        /// Show text on stderr as soon as Whisper decodes it, on a single line
+        /// that is replaced by the final text once the window is done.
+        #[arg(long = "partial")]
+        partial: bool,
+
+        /// Stop after this many seconds, as if Ctrl-C had been pressed.
+        #[arg(long = "max-duration", value_name = "SECONDS")]
+        max_duration: Option<f64>,
+    },
+    /// Transcribe an unbounded stream of raw PCM arriving on stdin, e.g.
+    /// `arecord -f S16_LE -r 16000 | hush stream -m base.en`.
+    Stream {
+        #[command(flatten)]
+        decode: DecodeArgs,
+
+        /// Sample encoding of the incoming PCM.
+        #[arg(short = 'f', long = "format", value_enum, default_value_t = PcmFormat::S16le)]
+        format: PcmFormat,
+
+        /// Sample rate of the incoming PCM.
+        #[arg(short = 'r', long = "sample-rate", default_value_t = 16000)]
+        sample_rate: u32,
+
+        /// Channel count of the incoming PCM.
+        #[arg(short = 'c', long = "channels", default_value_t = 1)]
+        channels: u16,
+
+        /// Suppress blank output and non-speech tokens, which cuts down on
+        /// hallucinated text over music or noise.
+        #[arg(long = "suppress-non-speech")]
+        suppress_non_speech: bool,
+
+        /// Length in seconds of the audio window transcribed at a time.
+        #[arg(short = 'w', long = "window-seconds", default_value_t = 3.0)]
+        window_seconds: f64,
+
+        /// RMS level above which audio counts as speech. Zero transcribes
+        /// every window regardless of level.
+        #[arg(long = "vad-threshold", default_value_t = 0.01)]
+        vad_threshold: f32,
+
+        /// Seconds of silence after speech before the window is transcribed.
+        #[arg(long = "silence-seconds", default_value_t = 0.8)]
+        silence_seconds: f64,
+    },
+    /// List known Whisper models or download one into the local cache.
+    Models {
+        #[arg(short, long)]
+        list: bool,
+
+        /// Download a model by name, e.g. `base.en`. Downloaded models can be
+        /// passed to --model by name.
+        #[arg(short, long, value_name = "NAME")]
+        download: Option<String>,
+    },
+    /// Measure how fast a model transcribes a file.
+    Bench {
+        #[command(flatten)]
+        decode: DecodeArgs,
+
+        #[arg(short = 'i', long, value_name = "INPUT_FILE")]
+        input_file: PathBuf,
+
+        /// Length in seconds of each chunk passed to Whisper.
+        #[arg(long = "chunk-seconds", default_value_t = 10.0)]
+        chunk_seconds: f64,
+
+        /// Seconds of audio shared between consecutive chunks.
+        #[arg(long = "overlap-seconds", default_value_t = 1.0)]
+        overlap_seconds: f64,
+
+        /// Also print the timing of every chunk.
+        #[arg(long = "per-chunk")]
+        per_chunk: bool,
+
+        /// Directory compressed input is decoded into while it is prepared
+        /// for Whisper. Defaults to the system's temporary directory.
+        #[arg(long = "tmp-dir", value_name = "DIR")]
+        tmp_dir: Option<PathBuf>,
+    },
+    /// Print details about an audio file or a model, e.g. to check why a
+    /// transcription comes out wrong.
+    Info {
+        #[arg(short = 'i', long, value_name = "INPUT_FILE")]
+        input_file: Option<PathBuf>,
+
+        /// Path to a GGML model, or the name of one fetched with `models --download`.
         #[arg(short = 'm', long = "model")]
-        model: PathBuf,
+        model: Option<PathBuf>,
+    }
+
+}
+
+impl Commands {
+    /// Fills in flags that weren't given on the command line from the config file.
+    fn apply_config(&mut self, config: &Config) {
+        let decode = match self {
+            Commands::Transcribe { decode, .. }
+            | Commands::Live { decode, .. }
+            | Commands::Stream { decode, .. }
+            | Commands::Bench { decode, .. } => decode,
+            _ => return,
+        };
+        if decode.model.is_none() {
+            decode.model.clone_from(&config.model);
+        }
+        if decode.language.is_none() {
+            decode.language.clone_from(&config.language);
+        }
+        if decode.threads.is_none() {
+            decode.threads = config.threads;
+        }
+    }
+}
+
+/// Writes a transcript in the given format with the options chosen on the
+/// command line.
+type TranscriptWriter<'a> = dyn Fn(&mut dyn Write, &[Segment], OutputFormat) -> std::io::Result<()> + 'a;
+
+/// Transcribes every audio file in `dir`, writing each transcript next to its
+/// source file or into `output_dir`, then prints a summary of the batch.
+/// Existing transcripts are left alone unless `force` is set. The model is
+/// loaded once by the caller and shared by every file; only the per-file
+/// Whisper states are created anew.
+fn transcribe_directory(dir: &Path, output_dir: Option<&Path>, force: bool, context: &WhisperContext, options: &TranscribeOptions,
+                        formats: &[OutputFormat], write: &TranscriptWriter) -> Result<(), anyhow::Error> {
+    let mut inputs: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    inputs.retain(|path| path.is_file() && is_audio_file(path));
+    inputs.sort();
+    if inputs.is_empty() {
+        anyhow::bail!("no audio files found in {}", dir.display());
+    }
+    if let Some(output_dir) = output_dir {
+        std::fs::create_dir_all(output_dir)?;
+    }
+
+    // Each result holds the number of segments written, or None when the
+    // file was skipped because one of its transcripts already exists.
+    let mut results = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.iter().enumerate() {
+        let transcripts: Vec<PathBuf> = formats.iter().map(|&format| transcript_path(input, output_dir, format)).collect();
+        if let Some(existing) = transcripts.iter().find(|transcript| transcript.exists()).filter(|_| !force) {
+            info!("[{}/{}] Skipping {}; {} already exists", index + 1, inputs.len(), input.display(), existing.display());
+            results.push((input, existing.clone(), Ok(None)));
+            continue;
+        }
+
+        info!("[{}/{}] Transcribing {}", index + 1, inputs.len(), input.display());
+        let result = transcribe_file(input, context, options).and_then(|segments| {
+            for (transcript, &format) in transcripts.iter().zip(formats) {
+                let mut output = BufWriter::new(File::create(transcript)?);
+                write(&mut output, &segments, format)?;
+                output.flush()?;
+            }
+            Ok(Some(segments.len()))
+        });
+        if let Err(err) = &result {
+            log::error!("failed to transcribe {}: {:#}", input.display(), err);
+        }
+        results.push((input, transcripts[0].clone(), result));
+        if options.aborted() {
+            break;
+        }
+    }
+
+    let failed = results.iter().filter(|(_, _, result)| result.is_err()).count();
+    let skipped = results.iter().filter(|(_, _, result)| matches!(result, Ok(None))).count();
+    for (input, transcript, result) in &results {
+        match result {
+            Ok(Some(segments)) => println!("ok      {} -> {} ({} segments)", input.display(), transcript.display(), segments),
+            Ok(None) => println!("skipped {} ({} exists)", input.display(), transcript.display()),
+            Err(err) => println!("failed  {}: {:#}", input.display(), err),
+        }
     }
+    println!("{} of {} files transcribed, {} skipped", results.len() - failed - skipped, results.len(), skipped);
 
+    if failed > 0 {
+        anyhow::bail!("{} of {} files failed", failed, results.len());
+    }
+    if skipped > 0 {
+        info!("Pass --force to overwrite existing transcripts.");
+    }
+    Ok(())
 }
 
-fn main() -> Result<(), anyhow::Error> {
-    let cli = Cli::parse();
+/// Where the transcript of `input` goes: beside it, or in `output_dir` when
+/// one is given, named after the input with the format's extension.
+fn transcript_path(input: &Path, output_dir: Option<&Path>, format: OutputFormat) -> PathBuf {
+    let transcript = input.with_extension(format.extension());
+    match output_dir {
+        Some(output_dir) => output_dir.join(transcript.file_name().unwrap()),
+        None => transcript,
+    }
+}
 
+/// Rejects format lists that would write two transcripts to the same file.
+fn check_formats(formats: &[OutputFormat]) -> Result<(), anyhow::Error> {
+    for (index, format) in formats.iter().enumerate() {
+        if formats[..index].iter().any(|other| other.extension() == format.extension()) {
+            anyhow::bail!("--format lists more than one format written as .{}", format.extension());
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<ExitCode, anyhow::Error> {
+    let mut cli = Cli::parse();
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => log::LevelFilter::Warn,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            log::Level::Error => writeln!(buf, "error: {}", record.args()),
+            log::Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
+    if cli.verbose == 0 {
+        silence_whisper_logging();
+    }
+
+    let config = Config::load()?;
+    if let Some(command) = cli.command.as_mut() {
+        command.apply_config(&config);
+    }
 
     match &cli.command {
-        Some(Commands::Device { list }) => {
+        Some(Commands::Device { list, formats, device_index, device_name, host }) => {
             if *list {
-                for device in list_input_devices(cpal::default_host().id()).into_iter().enumerate() {
-                    println!("{:?}: {:?}", device.0, device.1.name()?);
+                for (index, device) in describe_input_devices(resolve_host(host.as_deref()))?.into_iter().enumerate() {
+                    println!("{}: {}", index, device);
+                }
+            }
+            if *formats {
+                let device = select_input_device(host.as_deref(), *device_index, device_name.as_deref())?;
+                let info = describe_input_device(&device);
+                println!("{}:", info.name);
+                if info.configs.is_empty() {
+                    println!("  no supported input configurations");
+                }
+                for range in &info.configs {
+                    let is_default = info.default_config.as_ref().is_some_and(|config| {
+                        config.channels() == range.channels()
+                            && config.sample_format() == range.sample_format()
+                            && range.min_sample_rate() <= config.sample_rate()
+                            && config.sample_rate() <= range.max_sample_rate()
+                    });
+                    println!("  {} ch, {}-{} Hz, {:?}{}", range.channels(), range.min_sample_rate().0, range.max_sample_rate().0,
+                             range.sample_format(), if is_default { " (default)" } else { "" });
                 }
             }
             Ok(())
@@ -73,23 +691,57 @@ fn main() -> Result<(), anyhow::Error> {
             }
             Ok(())
         },
-        Some(Commands::Record { duration, device_index, output_file }) => {
-            let device = match device_index {
-                Some(device_index) => {
-                    get_input_device(Some(*device_index), Some(cpal::default_host().id()))
-                }
-                None => get_input_device(None, None),
-            };
+        Some(Commands::Record { duration, device_index, device_name, host, output_file, format, append, force, stop_on_silence, sample_rate, channels, meter, monitor, dry_run }) => {
+            let output_file = &expand_path_template(output_file, &chrono::Local::now());
+            if *append && *format != AudioFormat::Wav {
+                anyhow::bail!("--append only supports WAV output");
+            }
+            // A dry run writes nothing, so it only reports the clash.
+            let clobbers = output_file.exists() && !*append && !*force;
+            if clobbers && !*dry_run {
+                anyhow::bail!("{} already exists; pass --force to overwrite it, --append to add to it, or choose another path",
+                              output_file.display());
+            }
 
-            println!("Recording using input device {:?}", &device.name());
+            let device = select_input_device(host.as_deref(), *device_index, device_name.as_deref())?;
 
-            let config: cpal::SupportedStreamConfig =
-                cpal::SupportedStreamConfig::new(1, cpal::SampleRate(16000),
+            info!("Recording using input device {:?}", &device.name());
+
+            let desired: cpal::SupportedStreamConfig =
+                cpal::SupportedStreamConfig::new(channels.unwrap_or(1), cpal::SampleRate(sample_rate.unwrap_or(16000)),
                                                 cpal::SupportedBufferSize::Range { min: 256, max: 512 },
                                                 cpal::SampleFormat::F32);
+            let config = if sample_rate.is_some() || channels.is_some() {
+                check_input_config(&device, &desired)?;
+                desired
+            } else {
+                pick_config(&device, &desired)?
+            };
+
+            if *dry_run {
+                let levels = LevelMeter::new();
+                let stream = initialize_write_stream(device, Arc::new(Mutex::new(None)), config.clone(), None, Some(levels.clone()), None)?;
+                stream.play()?;
+                std::thread::sleep(DRY_RUN_DURATION);
+                drop(stream);
+                if levels.samples() == 0 {
+                    anyhow::bail!("no audio arrived from the input device within {} ms", DRY_RUN_DURATION.as_millis());
+                }
+                info!("Captured {} samples of {} channel(s) at {} Hz {}; peak: {:.1} dBFS, RMS: {:.1} dBFS",
+                      levels.samples(), config.channels(), config.sample_rate().0, config.sample_format(), dbfs(levels.peak()), dbfs(levels.rms()));
+                if clobbers {
+                    log::warn!("{} already exists; recording would need --force to overwrite it or --append to add to it",
+                               output_file.display());
+                }
+                info!("Dry run; {} was not written.", output_file.display());
+                return Ok(ExitCode::SUCCESS);
+            }
 
+            // A negotiated multichannel config is recorded as mono; only an
+            // explicit --channels keeps every channel.
+            let mono = channels.is_none() && config.channels() > 1;
             let wav_spec = hound::WavSpec {
-                channels: config.channels() as _,
+                channels: if mono { 1 } else { config.channels() as _ },
                 sample_rate: config.sample_rate().0 as _,
                 bits_per_sample: (config.sample_format().sample_size() * 8) as _,
                 sample_format: if config.sample_format().is_float() {
@@ -99,84 +751,429 @@ fn main() -> Result<(), anyhow::Error> {
                 },
             };
 
-            let writer = Arc::new(Mutex::new(Some(hound::WavWriter::create(
-                output_file, wav_spec,
-            )?)));
+            let audio_writer = if *format == AudioFormat::Flac {
+                AudioWriter::Flac(FlacWriter::create(output_file, wav_spec.channels, wav_spec.sample_rate, 16)?)
+            } else if *append && output_file.exists() {
+                let wav_writer = hound::WavWriter::append(output_file)?;
+                if wav_writer.spec() != wav_spec {
+                    anyhow::bail!("cannot append to {}: its format {:?} does not match this recording's {:?}",
+                                  output_file.display(), wav_writer.spec(), wav_spec);
+                }
+                AudioWriter::Wav(wav_writer)
+            } else {
+                AudioWriter::Wav(hound::WavWriter::create(output_file, wav_spec)?)
+            };
+            let writer = Arc::new(Mutex::new(Some(audio_writer)));
 
             let detatched_writer = writer.clone();
 
-            let stream = initialize_write_stream(device, detatched_writer, config);
-            stream.as_ref().unwrap().play()?;
+            let silence_monitor = stop_on_silence.map(|seconds| {
+                let samples_per_second = config.sample_rate().0 as f64 * config.channels() as f64;
+                SilenceMonitor::new(DEFAULT_SILENCE_THRESHOLD, (seconds * samples_per_second) as usize)
+            });
+            let silence_done = silence_monitor.as_ref().map(SilenceMonitor::done);
 
-            std::thread::sleep(std::time::Duration::from_secs(*duration));
+            let levels = LevelMeter::new();
+
+            let (monitor_stream, playback) = if *monitor {
+                let (stream, playback) = initialize_monitor_stream(default_output()?, &config)?;
+                stream.play()?;
+                (Some(stream), Some(playback))
+            } else {
+                (None, None)
+            };
+
+            let stream = initialize_write_stream(device, detatched_writer, config, silence_monitor, Some(levels.clone()), playback)?;
+            stream.play()?;
+
+            let started = std::time::Instant::now();
+            while started.elapsed() < std::time::Duration::from_secs(*duration) {
+                if silence_done.as_ref().is_some_and(|done| done.load(Ordering::SeqCst)) {
+                    info!("Stopping after {:.1} seconds of silence.", stop_on_silence.unwrap_or_default());
+                    break;
+                }
+                if *meter {
+                    // Map -60..0 dBFS onto the width of the bar.
+                    let db = dbfs(levels.level());
+                    let width = (((db + 60.0) / 60.0).clamp(0.0, 1.0) * 40.0) as usize;
+                    eprint!("\r[{:<40}] {:6.1} dBFS", "#".repeat(width), db);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            if *meter {
+                eprintln!();
+            }
             drop(stream);
+            drop(monitor_stream);
             writer.lock().unwrap().take().unwrap().finalize()?;
             let path: String = output_file.to_string_lossy().into_owned();
-            println!("Recording {} complete.", path);
+            info!("Recording {} complete.", path);
+            info!("peak: {:.1} dBFS, RMS: {:.1} dBFS", dbfs(levels.peak()), dbfs(levels.rms()));
+            let clipped = levels.clipped() as f64 / levels.samples().max(1) as f64;
+            if clipped > CLIPPING_WARN_FRACTION {
+                log::warn!("{} samples ({:.2}%) clipped; lower the input gain and record again for a cleaner transcript",
+                           levels.clipped(), clipped * 100.0);
+            }
 
             Ok(())
         },
-        Some(Commands::Transcribe { model, input_file }) => {
-            let model_path = model.as_os_str();
-            let context = WhisperContext::new_with_params(&model_path.to_str().unwrap(), WhisperContextParameters::default()).expect("Failed to load model.");
-
-            let mut state = context.create_state().expect("Failed to create state.");
+        Some(Commands::Transcribe { decode, input_files, output_file, output_dir, force, pcm_format, sample_rate, channels, format, suppress_non_speech, chunk_seconds, overlap_seconds, width, no_timestamps, word_timestamps, min_confidence, max_segment_length, split_on_word, highpass, normalize, trim_silence, start, end, time_offset, jobs, tmp_dir }) => {
+            check_formats(format)?;
+            if input_files.len() > 1 && input_files.iter().any(|input| input.is_dir() || input.as_os_str() == "-") {
+                anyhow::bail!("directories and `-` can only be transcribed on their own, not joined with other inputs");
+            }
+            // Transcripts of joined inputs are named after the first one.
+            let input_file = &input_files[0];
+            let context = load_model(&decode.model_path()?, decode.gpu, decode.load_timeout())?;
 
-            let  reader = hound::WavReader::open(input_file)?;
+            // Ctrl-C stops decoding the input or cancels the window Whisper is
+            // working on instead of waiting for it to finish.
+            let abort = Arc::new(AtomicBool::new(false));
+            let handler_abort = abort.clone();
+            ctrlc::set_handler(move || handler_abort.store(true, Ordering::SeqCst))?;
 
-            println!("Input file contains {} samples.", reader.len());
-            let samples : Vec<f32> = reader.into_samples::<f32>()
-                .map(|s| s.unwrap() as f32)
-                .collect();
+            let options = TranscribeOptions {
+                suppress_non_speech: *suppress_non_speech,
+                chunk_seconds: *chunk_seconds,
+                overlap_seconds: *overlap_seconds,
+                word_timestamps: *word_timestamps,
+                min_confidence: *min_confidence,
+                max_segment_len: *max_segment_length,
+                split_on_word: *split_on_word,
+                highpass_hz: *highpass,
+                normalize: *normalize,
+                trim_silence: *trim_silence,
+                start_seconds: *start,
+                end_seconds: *end,
+                time_offset_seconds: *time_offset,
+                tmp_dir: tmp_dir.clone().unwrap_or_else(std::env::temp_dir),
+                progress: true,
+                jobs: *jobs,
+                abort: Some(abort),
+                ..decode.into()
+            };
 
-            let chunk_size = 16000*10;
-            let mut chunks: Vec<Vec<f32>> = vec![vec![0.0; chunk_size]; samples.len() / chunk_size + 1];
-            for (i, sample) in samples.iter().enumerate() {
-                chunks[i / chunk_size][i % chunk_size] = *sample;
+            let write = |mut output: &mut dyn Write, segments: &[Segment], format: OutputFormat| write_segments(&mut output, segments, format, !*no_timestamps, *width);
+            if input_file.is_dir() {
+                if output_file.is_some() {
+                    anyhow::bail!("--output takes a single file; use --output-dir to choose where the transcripts of a directory go");
+                }
+                return transcribe_directory(input_file, output_dir.as_deref(), *force, &context, &options, format, &write).map(|()| ExitCode::SUCCESS);
             }
-                
+            // Where each format goes; None is stdout.
+            let outputs: Vec<(OutputFormat, Option<PathBuf>)> = match (output_dir, output_file) {
+                (Some(_), _) if input_file.as_os_str() == "-" => anyhow::bail!("--output-dir needs an input file to name the transcript after; use --output"),
+                (Some(output_dir), _) => {
+                    std::fs::create_dir_all(output_dir)?;
+                    let outputs: Vec<_> = format.iter().map(|&format| (format, Some(transcript_path(input_file, Some(output_dir), format)))).collect();
+                    if let Some(existing) = outputs.iter().filter_map(|(_, path)| path.as_ref()).find(|path| path.exists()) {
+                        if !*force {
+                            anyhow::bail!("{} already exists; pass --force to overwrite it", existing.display());
+                        }
+                    }
+                    outputs
+                },
+                (None, output_file) if format.len() == 1 => vec![(format[0], output_file.clone())],
+                (None, Some(output_file)) => format.iter().map(|&format| (format, Some(transcript_path(output_file, None, format)))).collect(),
+                (None, None) => anyhow::bail!("several formats need --output or --output-dir to name their files"),
+            };
 
-            println!("Using a buffer size of {} samples.", chunk_size);
-            for chunk in chunks {
-                let params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-                state.full(params, &chunk[..]).expect("Failed to run model.");
+            let (segments, timings) = if input_file.as_os_str() == "-" {
+                let spec = PcmSpec { format: *pcm_format, sample_rate: *sample_rate, channels: *channels };
+                transcribe_pcm_timed(std::io::stdin().lock(), spec, &context, &options)?
+            } else if input_files.len() > 1 {
+                transcribe_files_timed(input_files, &context, &options)?
+            } else {
+                transcribe_file_timed(input_file, &context, &options)?
+            };
 
-                let n_segments = state.full_n_segments().expect("Failed to get number of segments");
+            for (format, output_file) in outputs {
+                let mut output: Box<dyn Write> = match output_file {
+                    Some(output_file) => Box::new(BufWriter::new(File::create(output_file)?)),
+                    None => Box::new(std::io::stdout()),
+                };
 
-                for i in 0..n_segments {
-                    println!("{}", state.full_get_segment_text(i).expect("Failed to get text."));
-                }
+                write(&mut output, &segments, format)?;
+                output.flush()?;
             }
 
+            // No chunks were transcribed when the input held no audio at all,
+            // which is not the same as audio without speech.
+            if !timings.is_empty() && segments.iter().all(|segment| segment.text.trim().is_empty()) {
+                log::warn!("no speech found");
+                return Ok(ExitCode::from(NO_SPEECH_EXIT_CODE));
+            }
             Ok(())
         },
 
-        Some(Commands::Live { device_index, model }) => {
-            let device = match device_index {
-                Some(device_index) => {
-                    get_input_device(Some(*device_index), Some(cpal::default_host().id()))
-                }
-                None => get_input_device(None, None),
-            };
+        Some(Commands::Live { device_index, device_name, host, decode, keep_non_speech, max_segment_length, split_on_word, window_seconds, vad_threshold, silence_seconds, output_file, timestamps, json, partial, max_duration }) => {
+            let device = select_input_device(host.as_deref(), *device_index, device_name.as_deref())?;
 
-            println!("Recording using input device {:?}", &device.name());
+            info!("Recording using input device {:?}", &device.name());
 
-            let config: cpal::SupportedStreamConfig =
+            let desired: cpal::SupportedStreamConfig =
                 cpal::SupportedStreamConfig::new(1, cpal::SampleRate(16000),
                                                 cpal::SupportedBufferSize::Range { min: 256, max: 512 },
                                                 cpal::SampleFormat::F32);
+            let config = pick_config(&device, &desired)?;
+            if config != desired {
+                info!("Capturing {} channel(s) at {} Hz {}.", config.channels(), config.sample_rate().0, config.sample_format());
+            }
 
-            let buffer = Arc::new(Mutex::new(Buffer::new(model.to_path_buf(), 3 * 16000)));
+            if !(1.0..=30.0).contains(window_seconds) {
+                anyhow::bail!("--window-seconds must be between 1 and 30 seconds");
+            }
 
-            let stream = initialize_buffered_stream(device, buffer, config);
-            stream.as_ref().unwrap().play()?;
+            let (sender, receiver) = mpsc::channel();
+            let abort = Arc::new(AtomicBool::new(false));
+            let options = TranscribeOptions {
+                suppress_non_speech: !*keep_non_speech,
+                max_segment_len: *max_segment_length,
+                split_on_word: *split_on_word,
+                abort: Some(abort.clone()),
+                ..decode.into()
+            };
+            let mut buffer = Buffer::new(decode.model_path()?, (*window_seconds * 16000.0) as usize, options, sender, decode.gpu, decode.load_timeout())?;
+            buffer.vad_threshold = *vad_threshold;
+            buffer.vad_silence = (*silence_seconds * 16000.0) as usize;
+            let (partial_sender, partial_receiver) = mpsc::channel::<String>();
+            buffer.transcriber.partials = partial.then_some(partial_sender);
+            let buffer = Arc::new(Mutex::new(buffer));
+
+            // The transcript is flushed after every segment so that a long
+            // session survives the process being killed.
+            let mut transcript = match output_file {
+                Some(output_file) => Some(OpenOptions::new().create(true).append(true).open(output_file)?),
+                None => None,
+            };
+            // A status line on stderr shows how close the next window is, or
+            // with --partial what Whisper has decoded of the current one.
+            let show_progress = !cli.quiet && std::io::stderr().is_terminal();
+            let status_line = *partial || show_progress;
+            let mut emit = |segment: Segment| -> Result<(), anyhow::Error> {
+                if status_line {
+                    // Clear the status line so the final text starts on a clean line.
+                    eprint!("\r\x1b[2K");
+                }
+                let text = segment.text;
+                if *json {
+                    let line = serde_json::json!({ "text": text.trim(), "start_ms": segment.start_ms, "end_ms": segment.end_ms });
+                    let mut stdout = std::io::stdout().lock();
+                    writeln!(stdout, "{}", line)?;
+                    stdout.flush()?;
+                } else {
+                    println!("{}", text);
+                }
+                if let Some(transcript) = transcript.as_mut() {
+                    if *timestamps {
+                        writeln!(transcript, "[{}] {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), text.trim())?;
+                    } else {
+                        writeln!(transcript, "{}", text.trim())?;
+                    }
+                    transcript.flush()?;
+                }
+                Ok(())
+            };
+
+            // The first Ctrl-C stops capturing and transcribes what is left;
+            // a second one cancels that instead of waiting for Whisper.
+            let running = Arc::new(AtomicBool::new(true));
+            let handler_running = running.clone();
+            ctrlc::set_handler(move || if !handler_running.swap(false, Ordering::SeqCst) {
+                abort.store(true, Ordering::SeqCst);
+            })?;
+
+            let stream = initialize_buffered_stream(device, buffer.clone(), config)?;
+            stream.play()?;
+
+            let started = std::time::Instant::now();
+            while running.load(Ordering::SeqCst) {
+                if max_duration.is_some_and(|seconds| started.elapsed().as_secs_f64() >= seconds) {
+                    info!("Stopping after {} seconds.", max_duration.unwrap_or_default());
+                    break;
+                }
+                match receiver.recv_timeout(std::time::Duration::from_millis(100)) {
+                    Ok(segment) => {
+                        // Partials still queued belong to the window that just finished.
+                        partial_receiver.try_iter().for_each(drop);
+                        emit(segment)?;
+                    },
+                    Err(_) => if let Some(text) = partial_receiver.try_iter().last() {
+                        eprint!("\r\x1b[2K{}", text.trim());
+                    } else if show_progress {
+                        // The buffer stays locked while a window is being
+                        // transcribed; keep whatever the line shows until then.
+                        if let Ok(buffer) = buffer.try_lock() {
+                            eprint!("\r\x1b[2Knext window: {:3.0}%", buffer.progress().min(1.0) * 100.0);
+                        }
+                    },
+                }
+            }
+            if status_line {
+                eprint!("\r\x1b[2K");
+            }
+
+            // Stop capturing before transcribing whatever is left in the buffer.
+            if !running.load(Ordering::SeqCst) {
+                info!("Finishing up; press Ctrl-C again to skip the last window.");
+            }
+            drop(stream);
+            buffer.lock().unwrap().finish();
+            for segment in receiver.try_iter() {
+                emit(segment)?;
+            }
+
+            Ok(())
+        },
+        Some(Commands::Bench { decode, input_file, chunk_seconds, overlap_seconds, per_chunk, tmp_dir }) => {
+            let context = load_model(&decode.model_path()?, decode.gpu, decode.load_timeout())?;
+
+            let options = TranscribeOptions {
+                chunk_seconds: *chunk_seconds,
+                overlap_seconds: *overlap_seconds,
+                tmp_dir: tmp_dir.clone().unwrap_or_else(std::env::temp_dir),
+                ..decode.into()
+            };
+            let (_, timings) = transcribe_file_timed(input_file, &context, &options)?;
+
+            if *per_chunk {
+                for (index, timing) in timings.iter().enumerate() {
+                    println!("chunk {:>3}: {:.2}s of audio in {:.2}s, {} tokens",
+                             index + 1, timing.duration_ms as f64 / 1000.0, timing.elapsed.as_secs_f64(), timing.tokens);
+                }
+            }
+
+            let audio = timings.last().map_or(0.0, |timing| (timing.offset_ms + timing.duration_ms) as f64 / 1000.0);
+            let elapsed: f64 = timings.iter().map(|timing| timing.elapsed.as_secs_f64()).sum();
+            let tokens: usize = timings.iter().map(|timing| timing.tokens).sum();
+            let peak = timings.iter().map(|timing| timing.elapsed.as_secs_f64()).fold(0.0, f64::max);
+            println!("audio:            {:.2}s", audio);
+            println!("inference:        {:.2}s", elapsed);
+            println!("real-time factor: {:.2}x", audio / elapsed);
+            println!("tokens/sec:       {:.1}", tokens as f64 / elapsed);
+            println!("peak chunk:       {:.2}s", peak);
+
+            Ok(())
+        },
+        Some(Commands::Stream { decode, format, sample_rate, channels, suppress_non_speech, window_seconds, vad_threshold, silence_seconds }) => {
+            if !(1.0..=30.0).contains(window_seconds) {
+                anyhow::bail!("--window-seconds must be between 1 and 30 seconds");
+            }
+            if *sample_rate == 0 || *channels == 0 {
+                anyhow::bail!("--sample-rate and --channels must be non-zero");
+            }
+
+            let (sender, receiver) = mpsc::channel();
+            let options = TranscribeOptions {
+                suppress_non_speech: *suppress_non_speech,
+                ..decode.into()
+            };
+            let mut buffer = Buffer::new(decode.model_path()?, (*window_seconds * 16000.0) as usize, options, sender, decode.gpu, decode.load_timeout())?;
+            buffer.vad_threshold = *vad_threshold;
+            buffer.vad_silence = (*silence_seconds * 16000.0) as usize;
 
+            // Reads block while Whisper runs, so a fast producer is slowed down
+            // by the pipe rather than piling up audio in memory.
+            let mut stdin = std::io::stdin().lock();
+            let mut bytes = vec![0u8; 8192];
+            let mut pending = 0;
+            let mut samples = Vec::new();
+            let mut resampler = Resampler::new(*sample_rate, 16000);
             loop {
-                std::thread::sleep(std::time::Duration::from_secs(1));
+                let read = match stdin.read(&mut bytes[pending..]) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                let available = pending + read;
+                // Keep any partial sample or frame for the next read.
+                let whole = available - available % (format.bytes_per_sample() * *channels as usize);
+
+                samples.clear();
+                format.convert(&bytes[..whole], &mut samples);
+                let frame = resampler.process(&downmix(&samples, *channels as usize));
+                buffer.push_frame(&frame);
+                for segment in receiver.try_iter() {
+                    println!("{}", segment.text);
+                }
+
+                bytes.copy_within(whole..available, 0);
+                pending = available - whole;
+            }
+
+            buffer.finish();
+            for segment in receiver.try_iter() {
+                println!("{}", segment.text);
+            }
+
+            Ok(())
+        },
+        Some(Commands::Models { list, download }) => {
+            if *list {
+                for model in MODELS {
+                    let downloaded = cached_model_path(model.name)?.exists();
+                    println!("{}{}", model.name, if downloaded { " (downloaded)" } else { "" });
+                }
+            }
+            if let Some(name) = download {
+                let path = download_model(name)?;
+                info!("Downloaded {} to {}", name, path.display());
             }
+            Ok(())
+        },
+        Some(Commands::Info { input_file, model }) => {
+            if input_file.is_none() && model.is_none() {
+                anyhow::bail!("pass --input-file and/or --model");
+            }
+
+            if let Some(input_file) = input_file {
+                let is_wav = input_file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+                let (sample_rate, channels, samples) = if is_wav {
+                    let reader = hound::WavReader::open(input_file)?;
+                    let spec = reader.spec();
+                    let format = match spec.sample_format {
+                        hound::SampleFormat::Int => "integer",
+                        hound::SampleFormat::Float => "float",
+                    };
+                    println!("File:        {}", input_file.display());
+                    println!("Bit depth:   {} ({})", spec.bits_per_sample, format);
+                    (spec.sample_rate, spec.channels, reader.len() as u64)
+                } else {
+                    let audio = decode_audio(input_file)?;
+                    println!("File:        {}", input_file.display());
+                    (audio.sample_rate, audio.channels, audio.samples.len() as u64)
+                };
+                let frames = samples / channels.max(1) as u64;
+                println!("Sample rate: {} Hz", sample_rate);
+                println!("Channels:    {}", channels);
+                println!("Samples:     {}", samples);
+                println!("Duration:    {:.2} s", frames as f64 / sample_rate.max(1) as f64);
+                if sample_rate != 16000 || channels != 1 {
+                    println!("Note: Whisper expects 16000 Hz mono; this file will be downmixed and resampled before transcription.");
+                }
+            }
+
+            if let Some(model) = model {
+                if input_file.is_some() {
+                    println!();
+                }
+                let path = resolve_model(model)?;
+                let header = read_model_header(&path)?;
+                println!("Model:       {}", path.display());
+                println!("Size:        {}", header.size().unwrap_or("unknown"));
+                println!("Languages:   {}", if header.multilingual() { "multilingual" } else { "English only" });
+                println!("Weights:     {}", header.weight_type().unwrap_or("unknown"));
+                println!("Vocabulary:  {} tokens", header.n_vocab);
+                println!("Mel bands:   {}", header.n_mels);
+                println!("Encoder:     {} layers, {} heads, width {}, context {}", header.n_audio_layer, header.n_audio_head, header.n_audio_state, header.n_audio_ctx);
+                println!("Decoder:     {} layers, {} heads, width {}, context {}", header.n_text_layer, header.n_text_head, header.n_text_state, header.n_text_ctx);
+            }
+
+            Ok(())
         },
         None => {
             Ok(())
         }
-    }
+    }.map(|()| ExitCode::SUCCESS)
 }
@@ -0,0 +1,264 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::transcribe::GGML_MAGIC;
+
+/// Where GGML models are downloaded from.
+const MODEL_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// A GGML Whisper model published alongside whisper.cpp.
+pub struct ModelInfo {
+    pub name: &'static str,
+    /// SHA-1 of the model file, as listed in whisper.cpp's models/README.md.
+    pub sha1: &'static str,
+}
+
+pub const MODELS: &[ModelInfo] = &[
+    ModelInfo {
+        name: "tiny",
+        sha1: "bd577a113a864445d4c299885e0cb97d4ba92b5f",
+    },
+    ModelInfo {
+        name: "tiny.en",
+        sha1: "c78c86eb1a8faa21b369bcd33207cc90d64ae9df",
+    },
+    ModelInfo {
+        name: "base",
+        sha1: "465707469ff3a37a2b9b8d8f89f2f99de7299dac",
+    },
+    ModelInfo {
+        name: "base.en",
+        sha1: "137c40403d78fd54d454da0f9bd998f78703390c",
+    },
+    ModelInfo {
+        name: "small",
+        sha1: "55356645c2b361a969dfd0ef2c5a50d530afd8d5",
+    },
+    ModelInfo {
+        name: "small.en",
+        sha1: "db8a495a91d927739e50b3fc1cc4c6b8f6c2d022",
+    },
+    ModelInfo {
+        name: "medium",
+        sha1: "fd9727b6e1217c2f614f9b698455c4ffd82463b4",
+    },
+    ModelInfo {
+        name: "medium.en",
+        sha1: "8c30f0e44ce9560643ebd10bbe50cd20eafd3723",
+    },
+    ModelInfo {
+        name: "large-v1",
+        sha1: "b1caaf735c4cc1429223d5a74f0f4d0b9b59a299",
+    },
+    ModelInfo {
+        name: "large-v2",
+        sha1: "0f4c8e34f21cf1a914c59d8b3ce882345ad349d6",
+    },
+    ModelInfo {
+        name: "large-v3",
+        sha1: "ad82bf6a9043ceed055076d0fd39f5f186ff8062",
+    },
+];
+
+/// Looks up a known model by name.
+pub fn find_model(name: &str) -> Option<&'static ModelInfo> {
+    MODELS.iter().find(|model| model.name == name)
+}
+
+/// Directory downloaded models are kept in: `$XDG_CACHE_HOME/hush`, falling
+/// back to `~/.cache/hush`.
+pub fn cache_dir() -> Result<PathBuf, anyhow::Error> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".cache"))
+            .ok_or_else(|| anyhow!("cannot locate a cache directory; HOME is not set"))?,
+    };
+    Ok(base.join("hush"))
+}
+
+/// Path a model is stored at in the cache, whether or not it has been
+/// downloaded yet.
+pub fn cached_model_path(name: &str) -> Result<PathBuf, anyhow::Error> {
+    Ok(cache_dir()?.join(format!("ggml-{}.bin", name)))
+}
+
+/// Resolves the `--model` argument. Existing paths are used as given; a known
+/// model name such as `base.en` refers to the downloaded copy in the cache.
+pub fn resolve_model(model: &Path) -> Result<PathBuf, anyhow::Error> {
+    if model.exists() {
+        return Ok(model.to_path_buf());
+    }
+    let Some(info) = model.to_str().and_then(find_model) else {
+        return Ok(model.to_path_buf());
+    };
+
+    let path = cached_model_path(info.name)?;
+    if !path.exists() {
+        anyhow::bail!(
+            "model {} has not been downloaded; run `hush models --download {}`",
+            info.name,
+            info.name
+        );
+    }
+    Ok(path)
+}
+
+/// Downloads a known model into the cache, showing a progress bar on stderr
+/// and verifying its checksum. Returns the path of the downloaded file.
+pub fn download_model(name: &str) -> Result<PathBuf, anyhow::Error> {
+    let info = find_model(name).ok_or_else(|| {
+        let names: Vec<&str> = MODELS.iter().map(|model| model.name).collect();
+        anyhow!(
+            "unknown model {:?}; known models: {}",
+            name,
+            names.join(", ")
+        )
+    })?;
+
+    let path = cached_model_path(info.name)?;
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    let url = format!("{}/ggml-{}.bin", MODEL_URL, info.name);
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|err| anyhow!("failed to download {}: {}", url, err))?;
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+
+    let progress = match length {
+        Some(length) => ProgressBar::new(length),
+        None => ProgressBar::new_spinner(),
+    };
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )?
+        .progress_chars("=> "),
+    );
+    progress.set_message(format!("Downloading {}", info.name));
+
+    // Download next to the final path and only move the file into place once
+    // the checksum matches, so an interrupted download is never mistaken for
+    // a usable model.
+    let partial = path.with_extension("bin.part");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    let mut reader = progress.wrap_read(response.into_reader());
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+    }
+    writer.flush()?;
+    progress.finish_and_clear();
+
+    let digest = hasher.digest().to_string();
+    if digest != info.sha1 {
+        fs::remove_file(&partial)?;
+        anyhow::bail!(
+            "checksum mismatch for {}: expected {}, got {}",
+            info.name,
+            info.sha1,
+            digest
+        );
+    }
+    fs::rename(&partial, &path)?;
+
+    Ok(path)
+}
+
+/// Hyperparameters stored at the start of a GGML Whisper model file.
+#[derive(Clone, Debug)]
+pub struct ModelHeader {
+    pub n_vocab: i32,
+    pub n_audio_ctx: i32,
+    pub n_audio_state: i32,
+    pub n_audio_head: i32,
+    pub n_audio_layer: i32,
+    pub n_text_ctx: i32,
+    pub n_text_state: i32,
+    pub n_text_head: i32,
+    pub n_text_layer: i32,
+    pub n_mels: i32,
+    /// Weight type, with the quantization version in the thousands.
+    pub ftype: i32,
+}
+
+impl ModelHeader {
+    /// Model size inferred from the number of encoder layers.
+    pub fn size(&self) -> Option<&'static str> {
+        match self.n_audio_layer {
+            4 => Some("tiny"),
+            6 => Some("base"),
+            12 => Some("small"),
+            24 => Some("medium"),
+            32 => Some("large"),
+            _ => None,
+        }
+    }
+
+    /// English-only models have a smaller vocabulary without language tokens.
+    pub fn multilingual(&self) -> bool {
+        self.n_vocab >= 51865
+    }
+
+    /// Name of the type the weights are stored as.
+    pub fn weight_type(&self) -> Option<&'static str> {
+        match self.ftype % 1000 {
+            0 => Some("f32"),
+            1 => Some("f16"),
+            2 => Some("q4_0"),
+            3 => Some("q4_1"),
+            7 => Some("q8_0"),
+            8 => Some("q5_0"),
+            9 => Some("q5_1"),
+            10 => Some("q2_k"),
+            11 => Some("q3_k"),
+            12 => Some("q4_k"),
+            13 => Some("q5_k"),
+            14 => Some("q6_k"),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the hyperparameters of a GGML Whisper model without loading it.
+pub fn read_model_header(path: &Path) -> Result<ModelHeader, anyhow::Error> {
+    let mut bytes = [0; 48];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .map_err(|err| anyhow!("failed to read model {}: {}", path.display(), err))?;
+
+    let fields: Vec<i32> = bytes
+        .chunks_exact(4)
+        .map(|field| i32::from_le_bytes(field.try_into().unwrap()))
+        .collect();
+    if fields[0] as u32 != GGML_MAGIC {
+        anyhow::bail!("{} does not look like a GGML Whisper model", path.display());
+    }
+    Ok(ModelHeader {
+        n_vocab: fields[1],
+        n_audio_ctx: fields[2],
+        n_audio_state: fields[3],
+        n_audio_head: fields[4],
+        n_audio_layer: fields[5],
+        n_text_ctx: fields[6],
+        n_text_state: fields[7],
+        n_text_head: fields[8],
+        n_text_layer: fields[9],
+        n_mels: fields[10],
+        ftype: fields[11],
+    })
+}
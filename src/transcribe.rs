@@ -0,0 +1,1161 @@
+use std::ffi::{c_char, c_void};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use whisper_rs::{FullParams, WhisperContext, WhisperContextParameters, WhisperState};
+
+use crate::decode::{decode_audio_mono, decode_pcm, DecodedAudio, PcmSpec};
+use crate::dsp::{highpass, normalize, trim_silence};
+use crate::format::{Segment, Word};
+use crate::utils::{
+    dbfs, default_threads, downmix, read_wav_samples, resample, sampling_strategy,
+    strip_annotations, strip_overlap, DEFAULT_SILENCE_THRESHOLD,
+};
+
+/// Sample rate Whisper expects its input at.
+pub const SAMPLE_RATE: u32 = 16000;
+
+/// Number of samples Whisper processes in one pass (30 seconds).
+const WHISPER_WINDOW: usize = 30 * SAMPLE_RATE as usize;
+
+/// Peak level `--normalize` scales the input to (about -1 dBFS).
+const NORMALIZE_PEAK: f32 = 0.9;
+
+/// Magic number at the start of a GGML model file, read as a little-endian u32.
+pub(crate) const GGML_MAGIC: u32 = 0x67676d6c;
+
+/// Shortest input whisper.cpp accepts (one second).
+const MIN_CHUNK_SAMPLES: usize = SAMPLE_RATE as usize;
+
+/// Settings for running Whisper over a file or a live stream.
+#[derive(Clone, Debug)]
+pub struct TranscribeOptions {
+    /// Spoken language, or `auto` to let Whisper detect it.
+    pub language: String,
+    /// Translate the speech to English.
+    pub translate: bool,
+    /// Beam size for beam search; greedy decoding when `None`.
+    pub beam_size: Option<i32>,
+    /// Candidates decoded with greedy sampling, keeping the best one.
+    pub best_of: i32,
+    /// Initial prompt applied to every chunk.
+    pub prompt: Option<String>,
+    /// Sampling temperature of the first decoding attempt.
+    pub temperature: f32,
+    /// Amount the temperature is raised by when a decoding attempt fails
+    /// Whisper's quality checks; zero disables the fallback.
+    pub temperature_inc: f32,
+    /// Don't condition each window on the text decoded before it.
+    pub no_context: bool,
+    /// Emit at most one segment per window.
+    pub single_segment: bool,
+    /// Keep Whisper from emitting blank output and non-speech tokens.
+    pub suppress_non_speech: bool,
+    /// Remove bracketed annotations like `[Music]` from segment text.
+    pub strip_annotations: bool,
+    /// Number of threads Whisper runs on.
+    pub threads: i32,
+    /// Length of each chunk passed to Whisper.
+    pub chunk_seconds: f64,
+    /// Audio shared between consecutive chunks.
+    pub overlap_seconds: f64,
+    /// Collect per-token timings into `Segment::words`.
+    pub word_timestamps: bool,
+    /// Drop segments whose average token probability is lower than this.
+    pub min_confidence: Option<f32>,
+    /// Longest segment text in characters; longer segments are split.
+    pub max_segment_len: Option<usize>,
+    /// End segments on word boundaries instead of between any two tokens.
+    pub split_on_word: bool,
+    /// Cutoff of a high-pass filter applied to the audio before chunking.
+    pub highpass_hz: Option<f32>,
+    /// Scale the audio so that its peak sits just below full scale.
+    pub normalize: bool,
+    /// Drop long stretches of silence at the start and end of the audio.
+    pub trim_silence: bool,
+    /// Print per-chunk progress to stderr.
+    pub progress: bool,
+    /// Number of chunks transcribed concurrently, each on its own
+    /// `WhisperState` running `threads` threads. More than one job implies
+    /// `no_context`.
+    pub jobs: usize,
+    /// Offset in seconds to start transcribing at.
+    pub start_seconds: Option<f64>,
+    /// Offset in seconds to stop transcribing at.
+    pub end_seconds: Option<f64>,
+    /// Seconds added to every timestamp, e.g. the position of a clip in the
+    /// recording it was cut from.
+    pub time_offset_seconds: f64,
+    /// Directory compressed input is staged in while it is decoded.
+    pub tmp_dir: PathBuf,
+    /// Flag that, once set, cancels transcription, including a window
+    /// Whisper is in the middle of.
+    pub abort: Option<Arc<AtomicBool>>,
+}
+
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        TranscribeOptions {
+            language: "auto".to_string(),
+            translate: false,
+            beam_size: None,
+            best_of: 1,
+            prompt: None,
+            temperature: 0.0,
+            temperature_inc: 0.2,
+            no_context: false,
+            single_segment: false,
+            suppress_non_speech: false,
+            strip_annotations: false,
+            threads: default_threads(),
+            chunk_seconds: 10.0,
+            overlap_seconds: 1.0,
+            word_timestamps: false,
+            min_confidence: None,
+            max_segment_len: None,
+            split_on_word: false,
+            highpass_hz: None,
+            normalize: false,
+            trim_silence: false,
+            progress: false,
+            jobs: 1,
+            start_seconds: None,
+            end_seconds: None,
+            time_offset_seconds: 0.0,
+            tmp_dir: std::env::temp_dir(),
+            abort: None,
+        }
+    }
+}
+
+impl TranscribeOptions {
+    /// Whether the `abort` flag has been set.
+    pub fn aborted(&self) -> bool {
+        self.abort
+            .as_ref()
+            .is_some_and(|abort| abort.load(Ordering::SeqCst))
+    }
+}
+
+/// How long Whisper took to transcribe one chunk.
+#[derive(Clone, Debug)]
+pub struct ChunkTiming {
+    /// Start of the chunk relative to the start of the input.
+    pub offset_ms: i64,
+    /// Length of the audio in the chunk.
+    pub duration_ms: i64,
+    /// Wall-clock time spent in `WhisperState::full`.
+    pub elapsed: Duration,
+    /// Number of text tokens decoded, excluding special tokens.
+    pub tokens: usize,
+}
+
+/// Stops whisper.cpp from writing its log lines to stderr.
+pub fn silence_whisper_logging() {
+    // Generic over the log level so it matches whatever integer type the
+    // bindings use for the C enum on this platform.
+    unsafe extern "C" fn discard<L>(_level: L, _text: *const c_char, _user_data: *mut c_void) {}
+
+    // SAFETY: the callback never unwinds and ignores its pointers.
+    unsafe { whisper_rs::set_log_callback(Some(discard), std::ptr::null_mut()) };
+}
+
+/// How long loading a model may take before giving up, generous enough for
+/// the largest models on slow disks.
+pub const DEFAULT_LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Loads a GGML Whisper model, failing if that takes longer than `timeout`,
+/// e.g. because the file sits on a stalled network mount. `use_gpu` only has
+/// an effect when whisper-rs was built with a GPU backend such as CUDA or
+/// Metal.
+pub fn load_model(
+    path: &Path,
+    use_gpu: bool,
+    timeout: Duration,
+) -> Result<WhisperContext, anyhow::Error> {
+    let (sender, receiver) = mpsc::channel();
+    let model = path.to_path_buf();
+    // A read blocked on unresponsive storage can't be cancelled, so a load
+    // that times out is left to finish or hang on its own thread.
+    thread::spawn(move || sender.send(load_model_blocking(&model, use_gpu)).ok());
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => anyhow::bail!(
+            "model load timed out after {}s: {}",
+            timeout.as_secs(),
+            path.display()
+        ),
+        Err(RecvTimeoutError::Disconnected) => {
+            anyhow::bail!("loading model {} panicked", path.display())
+        }
+    }
+}
+
+fn load_model_blocking(path: &Path, use_gpu: bool) -> Result<WhisperContext, anyhow::Error> {
+    check_model_file(path)?;
+
+    let mut parameters = WhisperContextParameters::default();
+    parameters.use_gpu(use_gpu);
+    let started = Instant::now();
+    let context = WhisperContext::new_with_params(&path.to_string_lossy(), parameters)
+        .map_err(|err| anyhow!("failed to load model {}: {}", path.display(), err))?;
+    debug!(
+        "Loaded model {} in {:.2} s.",
+        path.display(),
+        started.elapsed().as_secs_f64()
+    );
+    Ok(context)
+}
+
+/// Checks that `path` is a file starting with the GGML magic number, so that a
+/// mistyped path fails with a readable error instead of inside whisper.cpp.
+fn check_model_file(path: &Path) -> Result<(), anyhow::Error> {
+    if !path.exists() {
+        anyhow::bail!("model file not found at {}", path.display());
+    }
+    if !path.is_file() {
+        anyhow::bail!("model path {} is not a file", path.display());
+    }
+
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_err(|err| anyhow!("failed to read model {}: {}", path.display(), err))?;
+    if u32::from_le_bytes(magic) != GGML_MAGIC {
+        anyhow::bail!("{} does not look like a GGML Whisper model", path.display());
+    }
+    Ok(())
+}
+
+/// Reads a WAV or compressed audio file as 16 kHz mono samples. Compressed
+/// files are decoded through a temporary file in `options.tmp_dir`, and
+/// decoding stops early once `options.abort` is set.
+pub fn read_audio(path: &Path, options: &TranscribeOptions) -> Result<Vec<f32>, anyhow::Error> {
+    let is_wav = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+    if is_wav {
+        let reader = hound::WavReader::open(path)
+            .map_err(|err| anyhow!("failed to open {}: {}", path.display(), err))?;
+
+        let spec = reader.spec();
+        debug!("Input file contains {} samples.", reader.len());
+        let audio = DecodedAudio {
+            samples: read_wav_samples(reader)
+                .map_err(|err| anyhow!("{}: {}", path.display(), err))?,
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+        };
+        Ok(to_whisper_input(audio))
+    } else {
+        let audio = decode_audio_mono(
+            path,
+            SAMPLE_RATE,
+            &options.tmp_dir,
+            options.abort.as_deref(),
+        )?;
+        debug!(
+            "Decoded {} samples from {}.",
+            audio.samples.len(),
+            path.display()
+        );
+        log_conversion(audio.source_rate, audio.source_channels);
+        Ok(audio.samples)
+    }
+}
+
+/// Reads headerless PCM from `reader` until EOF as 16 kHz mono samples.
+pub fn read_pcm<R: Read>(reader: R, spec: PcmSpec) -> Result<Vec<f32>, anyhow::Error> {
+    let audio = decode_pcm(reader, spec)?;
+    debug!("Read {} samples of raw PCM.", audio.samples.len());
+    Ok(to_whisper_input(audio))
+}
+
+/// Downmixes and resamples decoded audio to the 16 kHz mono Whisper expects.
+fn to_whisper_input(audio: DecodedAudio) -> Vec<f32> {
+    log_conversion(audio.sample_rate, audio.channels);
+    let mut samples = audio.samples;
+    if audio.channels > 1 {
+        samples = downmix(&samples, audio.channels as usize);
+    }
+    if audio.sample_rate != SAMPLE_RATE {
+        samples = resample(&samples, audio.sample_rate, SAMPLE_RATE);
+    }
+    samples
+}
+
+/// Reports the downmixing and resampling audio of this layout needs.
+fn log_conversion(sample_rate: u32, channels: u16) {
+    if channels > 1 {
+        info!("Downmixing {} channels to mono.", channels);
+    }
+    if sample_rate != SAMPLE_RATE {
+        // The resampler interpolates linearly without an anti-aliasing filter,
+        // which noticeably hurts accuracy for some recordings.
+        warn!(
+            "input is {} Hz but Whisper expects {} Hz; resampling with simple \
+             interpolation, so results may be degraded. For best accuracy convert \
+             the file first, e.g. `ffmpeg -i INPUT -ar {} OUTPUT.wav`.",
+            sample_rate, SAMPLE_RATE, SAMPLE_RATE
+        );
+    }
+}
+
+/// Transcribes an audio file, returning its segments with timestamps relative
+/// to the start of the file. Each call creates its own Whisper states, so one
+/// loaded `context` can be reused for any number of files.
+pub fn transcribe_file(
+    path: &Path,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<Vec<Segment>, anyhow::Error> {
+    let (segments, _) = transcribe_file_timed(path, context, options)?;
+    Ok(segments)
+}
+
+/// Like [`transcribe_file`], but also reports how long each chunk took.
+pub fn transcribe_file_timed(
+    path: &Path,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<(Vec<Segment>, Vec<ChunkTiming>), anyhow::Error> {
+    transcribe_audio(read_audio(path, options)?, context, options)
+}
+
+/// Transcribes several audio files as one recording, e.g. a talk recorded in
+/// parts. Each file is converted to 16 kHz mono and appended in order, so
+/// timestamps run on across file boundaries.
+pub fn transcribe_files<P: AsRef<Path>>(
+    paths: &[P],
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<Vec<Segment>, anyhow::Error> {
+    let (segments, _) = transcribe_files_timed(paths, context, options)?;
+    Ok(segments)
+}
+
+/// Like [`transcribe_files`], but also reports how long each chunk took.
+pub fn transcribe_files_timed<P: AsRef<Path>>(
+    paths: &[P],
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<(Vec<Segment>, Vec<ChunkTiming>), anyhow::Error> {
+    let mut samples = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let audio = read_audio(path, options)?;
+        debug!(
+            "{} starts at {:.2} s.",
+            path.display(),
+            samples.len() as f64 / SAMPLE_RATE as f64
+        );
+        samples.extend(audio);
+    }
+    transcribe_audio(samples, context, options)
+}
+
+/// Transcribes headerless PCM read from `reader` until EOF, e.g. audio piped
+/// in on stdin.
+pub fn transcribe_pcm<R: Read>(
+    reader: R,
+    spec: PcmSpec,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<Vec<Segment>, anyhow::Error> {
+    let (segments, _) = transcribe_pcm_timed(reader, spec, context, options)?;
+    Ok(segments)
+}
+
+/// Like [`transcribe_pcm`], but also reports how long each chunk took.
+pub fn transcribe_pcm_timed<R: Read>(
+    reader: R,
+    spec: PcmSpec,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<(Vec<Segment>, Vec<ChunkTiming>), anyhow::Error> {
+    transcribe_audio(read_pcm(reader, spec)?, context, options)
+}
+
+/// Transcribes mono samples already in memory, e.g. from the caller's own
+/// capture or decoder. Audio at a rate other than 16 kHz is resampled first;
+/// timestamps are relative to the first sample.
+pub fn transcribe_samples(
+    samples: &[f32],
+    sample_rate: u32,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<Vec<Segment>, anyhow::Error> {
+    if sample_rate == 0 {
+        anyhow::bail!("sample rate must be positive");
+    }
+    let audio = DecodedAudio {
+        samples: samples.to_vec(),
+        sample_rate,
+        channels: 1,
+    };
+    let (segments, _) = transcribe_audio(to_whisper_input(audio), context, options)?;
+    Ok(segments)
+}
+
+/// Applies the optional trim window and filters to 16 kHz mono input and
+/// transcribes it. Timestamps stay relative to the start of the input.
+fn transcribe_audio(
+    samples: Vec<f32>,
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<(Vec<Segment>, Vec<ChunkTiming>), anyhow::Error> {
+    // Whisper makes up text for silence, so don't hand it padding alone.
+    if samples.is_empty() {
+        warn!("no audio to transcribe");
+        return Ok((Vec::new(), Vec::new()));
+    }
+    if !(options.time_offset_seconds >= 0.0 && options.time_offset_seconds.is_finite()) {
+        anyhow::bail!("--time-offset must be a non-negative number of seconds");
+    }
+    let (mut samples, trim_offset_ms) = trim(samples, options.start_seconds, options.end_seconds)?;
+    let mut offset_ms = trim_offset_ms + (options.time_offset_seconds * 1000.0).round() as i64;
+    if let Some(cutoff_hz) = options.highpass_hz {
+        if !(cutoff_hz > 0.0 && cutoff_hz < SAMPLE_RATE as f32 / 2.0) {
+            anyhow::bail!(
+                "high-pass cutoff must be between 0 and {} Hz",
+                SAMPLE_RATE / 2
+            );
+        }
+        highpass(&mut samples, SAMPLE_RATE, cutoff_hz);
+    }
+    if options.trim_silence {
+        let kept = trim_silence(&samples, SAMPLE_RATE, DEFAULT_SILENCE_THRESHOLD);
+        if kept.is_empty() {
+            warn!("Input is silent; nothing to transcribe.");
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let dropped = samples.len() - kept.len();
+        if dropped > 0 {
+            info!(
+                "Trimmed {:.1}s of leading and trailing silence.",
+                dropped as f64 / SAMPLE_RATE as f64
+            );
+        }
+        offset_ms += (kept.start * 1000 / SAMPLE_RATE as usize) as i64;
+        samples.truncate(kept.end);
+        samples.drain(..kept.start);
+    }
+    if options.normalize {
+        match normalize(&mut samples, NORMALIZE_PEAK) {
+            Some(gain) => info!("Normalized input by {:+.1} dB.", dbfs(gain)),
+            None => warn!("Input is silent; skipping normalization."),
+        }
+    }
+    let (mut segments, mut timings) = transcribe_mono(&samples, context, options)?;
+    if offset_ms > 0 {
+        for segment in &mut segments {
+            segment.start_ms += offset_ms;
+            segment.end_ms += offset_ms;
+            for word in &mut segment.words {
+                word.start_ms += offset_ms;
+                word.end_ms += offset_ms;
+            }
+        }
+        for timing in &mut timings {
+            timing.offset_ms += offset_ms;
+        }
+    }
+    Ok((segments, timings))
+}
+
+/// Cuts 16 kHz samples down to the window between `start` and `end` seconds,
+/// returning the remaining samples and the offset of the first one in ms.
+fn trim(
+    mut samples: Vec<f32>,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<(Vec<f32>, i64), anyhow::Error> {
+    if start.is_none() && end.is_none() {
+        return Ok((samples, 0));
+    }
+
+    let length = samples.len() as f64 / SAMPLE_RATE as f64;
+    let start = start.unwrap_or(0.0);
+    let end = end.unwrap_or(length);
+    if start < 0.0 || end < 0.0 {
+        anyhow::bail!("--start and --end must not be negative");
+    }
+    if start >= end {
+        anyhow::bail!("--start ({}s) must come before --end ({}s)", start, end);
+    }
+    if start >= length {
+        anyhow::bail!(
+            "--start ({}s) is past the end of the input ({:.2}s)",
+            start,
+            length
+        );
+    }
+    if end > length {
+        anyhow::bail!(
+            "--end ({}s) is past the end of the input ({:.2}s)",
+            end,
+            length
+        );
+    }
+
+    let first = (start * SAMPLE_RATE as f64).round() as usize;
+    let last = ((end * SAMPLE_RATE as f64).round() as usize).min(samples.len());
+    samples.truncate(last);
+    samples.drain(..first);
+    Ok((samples, (first * 1000 / SAMPLE_RATE as usize) as i64))
+}
+
+/// Splits `samples` into chunks of at most `chunk_size` samples. Consecutive
+/// chunks start `chunk_size - overlap` samples apart, so each one repeats the
+/// last `overlap` samples of the previous chunk. The last chunk is left short
+/// rather than padded.
+fn chunk_samples(samples: &[f32], chunk_size: usize, overlap: usize) -> Vec<&[f32]> {
+    assert!(overlap < chunk_size, "overlap must be shorter than a chunk");
+    if samples.len() <= chunk_size {
+        return vec![samples];
+    }
+    let step = chunk_size - overlap;
+    let n_chunks = (samples.len() - overlap).div_ceil(step);
+    (0..n_chunks)
+        .map(|i| {
+            let start = i * step;
+            let end = (start + chunk_size).min(samples.len());
+            &samples[start..end]
+        })
+        .collect()
+}
+
+/// Options every chunk is decoded with when `jobs` workers share the work.
+/// A worker's Whisper state carries the text of the last chunk it decoded
+/// into the next one, and with several workers that is rarely the chunk just
+/// before it, so parallel runs decode each chunk without context. That keeps
+/// the transcript independent of how the chunks happen to be scheduled.
+fn chunk_options(options: &TranscribeOptions, jobs: usize) -> TranscribeOptions {
+    TranscribeOptions {
+        no_context: options.no_context || jobs > 1,
+        ..options.clone()
+    }
+}
+
+/// Runs every chunk through a transcriber on `jobs` threads, each with its
+/// own transcriber made by `new_worker`, and returns the results in chunk
+/// order regardless of which chunk finishes first. The transcriber is given
+/// the index of the chunk along with its samples.
+fn run_chunks<W, F>(
+    chunks: &[&[f32]],
+    jobs: usize,
+    options: &TranscribeOptions,
+    new_worker: W,
+) -> Result<Vec<ChunkResult>, anyhow::Error>
+where
+    W: Fn() -> Result<F, anyhow::Error> + Sync,
+    F: FnMut(usize, &[f32]) -> Result<ChunkResult, anyhow::Error>,
+{
+    let n_chunks = chunks.len();
+    // Workers pull chunk indices from `next` and store each result in its
+    // slot.
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<ChunkResult>>> =
+        Mutex::new((0..n_chunks).map(|_| None).collect());
+    let started = Instant::now();
+    let worker = || -> Result<(), anyhow::Error> {
+        let mut transcribe = new_worker()?;
+        loop {
+            let chunk_index = next.fetch_add(1, Ordering::SeqCst);
+            if chunk_index >= n_chunks || options.aborted() {
+                return Ok(());
+            }
+
+            if options.progress {
+                let completed = done.load(Ordering::SeqCst);
+                let elapsed = started.elapsed().as_secs_f64();
+                let remaining = if completed > 0 {
+                    format!(
+                        "{:.0}s",
+                        elapsed / completed as f64 * (n_chunks - completed) as f64
+                    )
+                } else {
+                    "unknown".to_string()
+                };
+                info!(
+                    "Transcribing chunk {} of {} ({:.0}s elapsed, {} remaining)",
+                    chunk_index + 1,
+                    n_chunks,
+                    elapsed,
+                    remaining
+                );
+            }
+
+            let result = match transcribe(chunk_index, chunks[chunk_index]) {
+                // An aborted window fails; the check below reports why.
+                Err(_) if options.aborted() => return Ok(()),
+                result => result.inspect_err(|_| next.store(n_chunks, Ordering::SeqCst))?,
+            };
+            results.lock().unwrap()[chunk_index] = Some(result);
+            done.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    if jobs <= 1 {
+        worker()?;
+    } else {
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..jobs).map(|_| scope.spawn(worker)).collect();
+            handles.into_iter().try_for_each(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("transcription worker panicked")))
+            })
+        })?;
+    }
+    if options.aborted() {
+        anyhow::bail!("transcription interrupted");
+    }
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every chunk is transcribed"))
+        .collect())
+}
+
+fn transcribe_mono(
+    samples: &[f32],
+    context: &WhisperContext,
+    options: &TranscribeOptions,
+) -> Result<(Vec<Segment>, Vec<ChunkTiming>), anyhow::Error> {
+    if options.chunk_seconds <= 0.0 {
+        anyhow::bail!("chunk length must be positive");
+    }
+    if options.chunk_seconds > 30.0 {
+        warn!("chunks longer than 30 seconds exceed Whisper's context and will be truncated");
+    }
+    let chunk_size = (options.chunk_seconds * SAMPLE_RATE as f64) as usize;
+    let overlap = (options.overlap_seconds * SAMPLE_RATE as f64) as usize;
+    if options.overlap_seconds < 0.0 || overlap >= chunk_size {
+        anyhow::bail!("overlap must be between 0 and the chunk length");
+    }
+
+    // Input that fits in Whisper's own window is transcribed in a single
+    // pass, which keeps the full context for short clips.
+    let step = chunk_size - overlap;
+    let chunks = if samples.len() <= WHISPER_WINDOW {
+        vec![samples]
+    } else {
+        chunk_samples(samples, chunk_size, overlap)
+    };
+    let n_chunks = chunks.len();
+
+    if n_chunks > 1 {
+        debug!("Using a buffer size of {} samples.", chunk_size);
+    }
+
+    let started = Instant::now();
+    let jobs = options.jobs.clamp(1, n_chunks.max(1));
+    let options = &chunk_options(options, jobs);
+    let results = run_chunks(&chunks, jobs, options, || {
+        let mut state = context.create_state()?;
+        Ok(move |chunk_index: usize, chunk: &[f32]| {
+            let offset_ms = (chunk_index * step * 1000 / SAMPLE_RATE as usize) as i64;
+            let result = transcribe_chunk(&mut state, chunk, offset_ms, options)?;
+            if chunk_index == 0 && options.language == "auto" {
+                let language = whisper_rs::get_lang_str(state.full_lang_id_from_state()?);
+                info!("Detected language: {}", language.unwrap_or("unknown"));
+            }
+            Ok(result)
+        })
+    })?;
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut timings = Vec::with_capacity(n_chunks);
+    for (chunk_segments, timing) in results {
+        timings.push(timing);
+        for mut segment in chunk_segments {
+            // Drop or trim segments that repeat audio already covered by the
+            // previous chunk.
+            if let Some(previous) = segments.last() {
+                if segment.end_ms <= previous.end_ms {
+                    continue;
+                }
+                if segment.start_ms < previous.end_ms {
+                    segment.text = strip_overlap(&previous.text, &segment.text);
+                    segment.start_ms = previous.end_ms;
+                    segment
+                        .words
+                        .retain(|word| word.start_ms >= previous.end_ms);
+                }
+            }
+            if !segment.text.trim().is_empty() {
+                segments.push(segment);
+            }
+        }
+    }
+
+    if options.progress {
+        info!(
+            "Transcribed {} chunks in {:.1}s.",
+            n_chunks,
+            started.elapsed().as_secs_f64()
+        );
+    }
+
+    let mut segments = merge_segments(segments);
+    // Merging can join segments back together past the limit Whisper split
+    // them at.
+    if let Some(max_len) = options.max_segment_len {
+        segments = split_long_segments(segments, max_len);
+    }
+    Ok((segments, timings))
+}
+
+/// Longest segment [`merge_segments`] builds by joining split sentences, so
+/// unpunctuated speech doesn't collapse into a single subtitle.
+const MAX_MERGED_MS: i64 = 30_000;
+
+/// Cleans up segments collected from overlapping chunks. A segment that
+/// overlaps the previous one in time loses the words repeating its end, or is
+/// dropped when all of them do, and a segment that directly continues an
+/// unfinished sentence is joined onto it.
+pub fn merge_segments(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+    for mut segment in segments {
+        let Some(previous) = merged.last_mut() else {
+            merged.push(segment);
+            continue;
+        };
+
+        // Only segments that overlap the previous one in time can repeat it;
+        // the same words later on are speech in their own right.
+        if segment.start_ms < previous.end_ms {
+            let text = strip_overlap(&previous.text, &segment.text);
+            if text.is_empty() {
+                previous.end_ms = previous.end_ms.max(segment.end_ms);
+                continue;
+            }
+            segment.text = format!(" {}", text);
+            segment.start_ms = segment.start_ms.max(previous.end_ms);
+            segment
+                .words
+                .retain(|word| word.start_ms >= previous.end_ms);
+        }
+
+        let unfinished = !previous
+            .text
+            .trim_end()
+            .ends_with(['.', '!', '?', '\u{2026}']);
+        if unfinished
+            && segment.start_ms <= previous.end_ms
+            && segment.end_ms - previous.start_ms <= MAX_MERGED_MS
+        {
+            let previous_ms = (previous.end_ms - previous.start_ms).max(1) as f32;
+            let segment_ms = (segment.end_ms - segment.start_ms).max(1) as f32;
+            previous.confidence = (previous.confidence * previous_ms
+                + segment.confidence * segment_ms)
+                / (previous_ms + segment_ms);
+            previous.text = format!("{} {}", previous.text.trim_end(), segment.text.trim());
+            previous.end_ms = segment.end_ms;
+            previous.words.append(&mut segment.words);
+        } else {
+            merged.push(segment);
+        }
+    }
+    merged
+}
+
+/// Splits segments whose text is longer than `max_len` characters at word
+/// boundaries, sharing the segment's time span between the pieces in
+/// proportion to their length. Words longer than `max_len` are cut.
+pub fn split_long_segments(segments: Vec<Segment>, max_len: usize) -> Vec<Segment> {
+    let max_len = max_len.max(1);
+    let mut split = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if segment.text.trim().chars().count() <= max_len {
+            split.push(segment);
+            continue;
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut line = String::new();
+        for word in segment.text.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while word.len() > max_len {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                lines.push(word.drain(..max_len).collect());
+            }
+            if word.is_empty() {
+                continue;
+            }
+            if !line.is_empty() && line.chars().count() + 1 + word.len() > max_len {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+
+        let total: i64 = lines.iter().map(|line| line.chars().count() as i64).sum();
+        let duration = segment.end_ms - segment.start_ms;
+        let mut words = segment.words.into_iter().peekable();
+        let mut before = 0;
+        for (index, line) in lines.iter().enumerate() {
+            let start_ms = segment.start_ms + duration * before / total;
+            before += line.chars().count() as i64;
+            let end_ms = if index + 1 == lines.len() {
+                segment.end_ms
+            } else {
+                segment.start_ms + duration * before / total
+            };
+            let mut piece_words = Vec::new();
+            while let Some(word) =
+                words.next_if(|word| index + 1 == lines.len() || word.start_ms < end_ms)
+            {
+                piece_words.push(word);
+            }
+            split.push(Segment {
+                start_ms,
+                end_ms,
+                text: format!(" {}", line),
+                confidence: segment.confidence,
+                words: piece_words,
+            });
+        }
+    }
+    split
+}
+
+/// Builds the Whisper parameters shared by file and live transcription.
+pub fn build_params(options: &TranscribeOptions) -> FullParams<'_, '_> {
+    let mut params = FullParams::new(sampling_strategy(options.beam_size, options.best_of));
+    params.set_language(Some(&options.language));
+    params.set_translate(options.translate);
+    if let Some(prompt) = &options.prompt {
+        params.set_initial_prompt(prompt);
+    }
+    params.set_temperature(options.temperature);
+    params.set_temperature_inc(options.temperature_inc);
+    params.set_no_context(options.no_context);
+    params.set_single_segment(options.single_segment);
+    if options.suppress_non_speech {
+        params.set_suppress_blank(true);
+        params.set_suppress_non_speech_tokens(true);
+    }
+    params.set_n_threads(options.threads);
+    // whisper.cpp only honours max_len when token timestamps are enabled.
+    params.set_token_timestamps(options.word_timestamps || options.max_segment_len.is_some());
+    if let Some(max_len) = options.max_segment_len {
+        params.set_max_len(max_len as i32);
+    }
+    params.set_split_on_word(options.split_on_word);
+    if let Some(abort) = &options.abort {
+        // The flag is owned by `options`, which the returned parameters
+        // borrow, so the pointer stays valid for as long as Whisper can call
+        // back with it.
+        unsafe {
+            params.set_abort_callback(Some(abort_requested));
+            params.set_abort_callback_user_data(Arc::as_ptr(abort) as *mut c_void);
+        }
+    }
+    params
+}
+
+/// Abort callback polled by whisper.cpp, with `user_data` pointing at the
+/// [`TranscribeOptions::abort`] flag.
+unsafe extern "C" fn abort_requested(user_data: *mut c_void) -> bool {
+    (*(user_data as *const AtomicBool)).load(Ordering::SeqCst)
+}
+
+/// Segments of a chunk along with how long it took.
+type ChunkResult = (Vec<Segment>, ChunkTiming);
+
+/// Runs Whisper over a single chunk, returning its segments shifted by
+/// `offset_ms` along with how long the chunk took.
+fn transcribe_chunk(
+    state: &mut WhisperState,
+    chunk: &[f32],
+    offset_ms: i64,
+    options: &TranscribeOptions,
+) -> Result<ChunkResult, anyhow::Error> {
+    let params = build_params(options);
+    let started = Instant::now();
+    // whisper.cpp refuses input shorter than a second, so a short final
+    // chunk gets just enough trailing silence to reach that length.
+    if chunk.len() < MIN_CHUNK_SAMPLES {
+        let mut padded = chunk.to_vec();
+        padded.resize(MIN_CHUNK_SAMPLES, 0.0);
+        state.full(params, &padded)?;
+    } else {
+        state.full(params, chunk)?;
+    }
+    let mut timing = ChunkTiming {
+        offset_ms,
+        duration_ms: (chunk.len() * 1000 / SAMPLE_RATE as usize) as i64,
+        elapsed: started.elapsed(),
+        tokens: 0,
+    };
+
+    // Whisper timestamps are in centiseconds relative to the chunk.
+    let mut segments = Vec::new();
+    for i in 0..state.full_n_segments()? {
+        let mut text = state.full_get_segment_text(i)?;
+        if options.strip_annotations {
+            text = strip_annotations(&text);
+            if text.trim().is_empty() {
+                continue;
+            }
+        }
+        let mut segment = Segment {
+            start_ms: offset_ms + state.full_get_segment_t0(i)? * 10,
+            end_ms: offset_ms + state.full_get_segment_t1(i)? * 10,
+            text,
+            confidence: 0.0,
+            words: Vec::new(),
+        };
+
+        let mut probabilities = Vec::new();
+        for j in 0..state.full_n_tokens(i)? {
+            let text = state.full_get_token_text(i, j)?;
+            // Skip special tokens such as [_BEG_] and <|endoftext|>.
+            if text.starts_with("[_") || text.starts_with("<|") {
+                continue;
+            }
+            let data = state.full_get_token_data(i, j)?;
+            probabilities.push(data.p);
+            timing.tokens += 1;
+            if options.word_timestamps {
+                segment.words.push(Word {
+                    start_ms: offset_ms + data.t0 * 10,
+                    end_ms: offset_ms + data.t1 * 10,
+                    text,
+                });
+            }
+        }
+        if !probabilities.is_empty() {
+            segment.confidence = probabilities.iter().sum::<f32>() / probabilities.len() as f32;
+        }
+        if options
+            .min_confidence
+            .is_some_and(|min_confidence| segment.confidence < min_confidence)
+        {
+            continue;
+        }
+
+        segments.push(segment);
+    }
+
+    Ok((segments, timing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+        Segment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            confidence: 1.0,
+            words: Vec::new(),
+        }
+    }
+
+    fn ramp(len: usize) -> Vec<f32> {
+        (0..len).map(|i| i as f32).collect()
+    }
+
+    #[test]
+    fn chunk_samples_keeps_short_input_whole() {
+        let samples = ramp(30);
+        assert_eq!(chunk_samples(&samples, 40, 10), vec![&samples[..]]);
+        assert_eq!(chunk_samples(&[], 40, 10), vec![&[] as &[f32]]);
+    }
+
+    #[test]
+    fn chunk_samples_overlaps_consecutive_chunks() {
+        let samples = ramp(100);
+        let chunks = chunk_samples(&samples, 40, 10);
+        assert_eq!(
+            chunks,
+            vec![&samples[0..40], &samples[30..70], &samples[60..100]]
+        );
+    }
+
+    #[test]
+    fn chunk_samples_leaves_last_chunk_short() {
+        let samples = ramp(95);
+        let chunks = chunk_samples(&samples, 40, 0);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2], &samples[80..95]);
+    }
+
+    #[test]
+    fn chunk_samples_drops_no_samples() {
+        for len in [41, 59, 60, 61, 1000, 1001] {
+            let samples = ramp(len);
+            let chunks = chunk_samples(&samples, 20, 5);
+            assert!(chunks.iter().all(|chunk| chunk.len() <= 20));
+            // The trailing chunk must add samples beyond the overlap.
+            assert!(chunks.last().unwrap().len() > 5);
+            let mut joined = chunks[0].to_vec();
+            for chunk in &chunks[1..] {
+                joined.extend_from_slice(&chunk[5..]);
+            }
+            assert_eq!(joined, samples, "length {}", len);
+        }
+    }
+
+    /// Runs `run_chunks` with a stand-in transcriber that names each chunk
+    /// after its index and first sample, taking longer over earlier chunks so
+    /// parallel workers finish out of order.
+    fn run_fake_chunks(chunks: &[&[f32]], jobs: usize) -> Vec<Segment> {
+        let options = TranscribeOptions {
+            jobs,
+            ..Default::default()
+        };
+        let results = run_chunks(chunks, jobs, &options, || {
+            Ok(|index: usize, chunk: &[f32]| {
+                thread::sleep(Duration::from_millis((chunks.len() - index) as u64));
+                let start_ms = index as i64 * 1000;
+                let text = format!("chunk {} at {}", index, chunk[0]);
+                let timing = ChunkTiming {
+                    offset_ms: start_ms,
+                    duration_ms: 1000,
+                    elapsed: Duration::ZERO,
+                    tokens: 0,
+                };
+                Ok((vec![segment(start_ms, start_ms + 1000, &text)], timing))
+            })
+        })
+        .unwrap();
+        results
+            .into_iter()
+            .flat_map(|(segments, _)| segments)
+            .collect()
+    }
+
+    #[test]
+    fn run_chunks_returns_the_same_chunks_in_order_for_any_number_of_jobs() {
+        let samples = ramp(1000);
+        let chunks = chunk_samples(&samples, 100, 10);
+        let sequential = run_fake_chunks(&chunks, 1);
+        assert_eq!(sequential.len(), chunks.len());
+        assert_eq!(sequential[3].text, "chunk 3 at 270");
+        assert_eq!(run_fake_chunks(&chunks, 4), sequential);
+    }
+
+    #[test]
+    fn parallel_jobs_decode_without_context() {
+        let options = TranscribeOptions::default();
+        assert!(!chunk_options(&options, 1).no_context);
+        assert!(chunk_options(&options, 4).no_context);
+    }
+
+    #[test]
+    fn split_long_segments_splits_at_word_boundaries_with_monotonic_timing() {
+        let split = split_long_segments(
+            vec![
+                segment(0, 3000, " The quick brown fox jumps over the lazy dog."),
+                segment(3000, 4000, " Short."),
+            ],
+            20,
+        );
+        let texts: Vec<&str> = split.iter().map(|segment| segment.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                " The quick brown fox",
+                " jumps over the lazy",
+                " dog.",
+                " Short."
+            ]
+        );
+        assert_eq!(split[0].start_ms, 0);
+        assert_eq!(split[2].end_ms, 3000);
+        for pair in split.windows(2) {
+            assert!(pair[0].start_ms < pair[0].end_ms);
+            assert_eq!(pair[0].end_ms, pair[1].start_ms);
+        }
+    }
+
+    #[test]
+    fn merge_segments_drops_repeated_segment() {
+        let merged = merge_segments(vec![
+            segment(0, 4000, " The quick brown fox."),
+            segment(3000, 4000, " brown fox."),
+        ]);
+        assert_eq!(merged, vec![segment(0, 4000, " The quick brown fox.")]);
+    }
+
+    #[test]
+    fn merge_segments_trims_overlapping_text() {
+        let merged = merge_segments(vec![
+            segment(0, 4000, " The quick brown fox."),
+            segment(3500, 6000, " brown fox. Jumps over the dog."),
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                segment(0, 4000, " The quick brown fox."),
+                segment(4000, 6000, " Jumps over the dog."),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_segments_joins_split_sentence() {
+        let merged = merge_segments(vec![
+            segment(0, 2000, " The quick brown"),
+            segment(2000, 4000, " fox jumps."),
+            segment(4000, 5000, " Then it sleeps."),
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                segment(0, 4000, " The quick brown fox jumps."),
+                segment(4000, 5000, " Then it sleeps."),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_segments_keeps_repeated_words_after_a_gap() {
+        let segments = vec![
+            segment(0, 1000, " I said no."),
+            segment(8000, 9000, " No way."),
+        ];
+        assert_eq!(merge_segments(segments.clone()), segments);
+    }
+
+    #[test]
+    fn merge_segments_keeps_repeated_segment_after_a_gap() {
+        let segments = vec![
+            segment(0, 1000, " Thank you."),
+            segment(20000, 21000, " Thank you."),
+        ];
+        assert_eq!(merge_segments(segments.clone()), segments);
+    }
+
+    #[test]
+    fn merge_segments_keeps_separate_sentences_with_a_gap() {
+        let segments = vec![
+            segment(0, 2000, " Hello there"),
+            segment(3000, 4000, " General Kenobi."),
+        ];
+        assert_eq!(merge_segments(segments.clone()), segments);
+    }
+}
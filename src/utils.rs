@@ -0,0 +1,1317 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use cpal::traits::DeviceTrait;
+use cpal::{FromSample, Sample};
+use regex::Regex;
+use whisper_rs::{SamplingStrategy, SegmentCallbackData, WhisperContext};
+
+use crate::flac::FlacWriter;
+use crate::format::Segment;
+use crate::transcribe::{build_params, load_model, TranscribeOptions};
+
+pub type AudioWriterHandle = Arc<Mutex<Option<AudioWriter>>>;
+
+/// Container formats the Record command can write.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    /// Lossless FLAC with 16-bit samples.
+    Flac,
+}
+
+/// Destination of recorded audio.
+pub enum AudioWriter {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+}
+
+impl AudioWriter {
+    fn write_sample<S>(&mut self, sample: S)
+    where
+        S: Sample + hound::Sample,
+        i16: FromSample<S>,
+    {
+        match self {
+            AudioWriter::Wav(writer) => {
+                writer.write_sample(sample).ok();
+            }
+            AudioWriter::Flac(writer) => {
+                writer.write_sample(i16::from_sample(sample) as i32).ok();
+            }
+        }
+    }
+
+    /// Number of channels declared in the file's header.
+    pub fn channels(&self) -> usize {
+        match self {
+            AudioWriter::Wav(writer) => writer.spec().channels as usize,
+            AudioWriter::Flac(writer) => writer.channels() as usize,
+        }
+    }
+
+    pub fn finalize(self) -> Result<(), anyhow::Error> {
+        match self {
+            AudioWriter::Wav(writer) => writer.finalize()?,
+            AudioWriter::Flac(writer) => writer.finalize()?,
+        }
+        Ok(())
+    }
+}
+
+/// RMS level below which captured audio is treated as silence.
+pub const DEFAULT_SILENCE_THRESHOLD: f32 = 0.01;
+
+/// Watches captured audio and raises a flag once a run of silence follows
+/// some sound.
+#[derive(Clone)]
+pub struct SilenceMonitor {
+    threshold: f32,
+    limit: usize,
+    heard_sound: bool,
+    silent_samples: usize,
+    done: Arc<AtomicBool>,
+}
+
+impl SilenceMonitor {
+    /// `limit` is the number of consecutive silent samples that ends the
+    /// recording.
+    pub fn new(threshold: f32, limit: usize) -> SilenceMonitor {
+        SilenceMonitor {
+            threshold,
+            limit,
+            heard_sound: false,
+            silent_samples: 0,
+            done: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag that is set once the silence limit has been reached.
+    pub fn done(&self) -> Arc<AtomicBool> {
+        self.done.clone()
+    }
+
+    pub fn observe<T>(&mut self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        if input.is_empty() {
+            return;
+        }
+        let energy: f32 = input
+            .iter()
+            .map(|&sample| f32::from_sample(sample).powi(2))
+            .sum();
+        if (energy / input.len() as f32).sqrt() >= self.threshold {
+            self.heard_sound = true;
+            self.silent_samples = 0;
+        } else if self.heard_sound {
+            self.silent_samples += input.len();
+            if self.silent_samples >= self.limit {
+                self.done.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+/// Absolute sample value from which a sample counts as clipped. Integer
+/// samples at their maximum convert to just below 1.0.
+pub const CLIP_LEVEL: f32 = 0.99;
+
+/// Shares the peak level of the most recent block of captured audio with
+/// another thread, e.g. to draw a level meter, and accumulates the peak and
+/// RMS level of everything captured so far along with how much of it clipped.
+#[derive(Clone, Default)]
+pub struct LevelMeter {
+    /// Bits of the latest peak as an `f32`.
+    peak: Arc<AtomicU32>,
+    totals: Arc<Mutex<LevelTotals>>,
+}
+
+#[derive(Default)]
+struct LevelTotals {
+    peak: f32,
+    sum_squares: f64,
+    samples: u64,
+    clipped: u64,
+}
+
+impl LevelMeter {
+    pub fn new() -> LevelMeter {
+        LevelMeter::default()
+    }
+
+    pub fn observe<T>(&self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        let mut peak: f32 = 0.0;
+        let mut sum_squares = 0.0;
+        let mut clipped = 0;
+        for &sample in input {
+            let sample = f32::from_sample(sample);
+            peak = peak.max(sample.abs());
+            sum_squares += (sample as f64).powi(2);
+            if sample.abs() >= CLIP_LEVEL {
+                clipped += 1;
+            }
+        }
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+
+        let mut totals = self.totals.lock().unwrap();
+        totals.peak = totals.peak.max(peak);
+        totals.sum_squares += sum_squares;
+        totals.samples += input.len() as u64;
+        totals.clipped += clipped;
+    }
+
+    /// Peak absolute sample value of the latest block, between 0.0 and 1.0.
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// Largest absolute sample value seen so far.
+    pub fn peak(&self) -> f32 {
+        self.totals.lock().unwrap().peak
+    }
+
+    /// Number of samples seen so far, across all channels.
+    pub fn samples(&self) -> u64 {
+        self.totals.lock().unwrap().samples
+    }
+
+    /// Number of samples seen so far that reached [`CLIP_LEVEL`].
+    pub fn clipped(&self) -> u64 {
+        self.totals.lock().unwrap().clipped
+    }
+
+    /// RMS level of all samples seen so far.
+    pub fn rms(&self) -> f32 {
+        let totals = self.totals.lock().unwrap();
+        if totals.samples == 0 {
+            return 0.0;
+        }
+        (totals.sum_squares / totals.samples as f64).sqrt() as f32
+    }
+}
+
+/// Hands captured audio to an output stream so it can be heard while
+/// recording. Only the most recent audio is kept, so playback never falls
+/// far behind the microphone.
+#[derive(Clone)]
+pub struct Monitor {
+    /// Mono samples at the output sample rate waiting to be played.
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    capacity: usize,
+    input_channels: usize,
+    /// Converts captured audio to the output sample rate.
+    resampler: Arc<Mutex<Resampler>>,
+}
+
+impl Monitor {
+    /// Queues a block of captured audio for playback.
+    pub fn push<T>(&self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        let mut frame: Vec<f32> = input
+            .iter()
+            .map(|&sample| f32::from_sample(sample))
+            .collect();
+        if self.input_channels > 1 {
+            frame = downmix(&frame, self.input_channels);
+        }
+        let frame = self.resampler.lock().unwrap().process(&frame);
+
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(frame);
+        let excess = queue.len().saturating_sub(self.capacity);
+        queue.drain(..excess);
+    }
+
+    /// Fills an interleaved output buffer, playing silence once the queue
+    /// runs dry.
+    fn fill<T>(&self, output: &mut [T], channels: usize)
+    where
+        T: Sample + FromSample<f32>,
+    {
+        let mut queue = self.queue.lock().unwrap();
+        for frame in output.chunks_mut(channels) {
+            let sample = T::from_sample(queue.pop_front().unwrap_or(0.0));
+            frame.fill(sample);
+        }
+    }
+}
+
+/// Converts a linear level relative to full scale into dBFS.
+pub fn dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-10).log10()
+}
+
+/// Parses a duration such as `90`, `45s`, `1m30s` or `2h` into seconds. Bare
+/// numbers are seconds.
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(seconds);
+    }
+
+    let invalid = || {
+        format!(
+            "invalid duration {:?}; use e.g. 90, 45s, 1m30s or 2h",
+            value
+        )
+    };
+    let mut seconds: u64 = 0;
+    let mut digits = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let amount: u64 = digits.parse().map_err(|_| invalid())?;
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|amount| seconds.checked_add(amount))
+            .ok_or_else(invalid)?;
+        digits.clear();
+    }
+    if !digits.is_empty() || value.is_empty() {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
+/// strftime specifiers [`expand_path_template`] replaces; a `%` followed by
+/// anything else is kept as it is.
+const PATH_SPECIFIERS: &str = "YCymbBhdejaAuwUWGgVHIklMSpPfsZzFTDRr%";
+
+/// Expands strftime-style placeholders such as `%Y%m%d-%H%M%S` in `path`
+/// using the local time `now`. Padding flags like `%-d` are supported. Text
+/// that isn't a known placeholder, e.g. the `%` in `rec-100%.wav`, is left
+/// unchanged.
+pub fn expand_path_template(path: &Path, now: &chrono::DateTime<chrono::Local>) -> PathBuf {
+    let Some(template) = path.to_str().filter(|template| template.contains('%')) else {
+        return path.to_path_buf();
+    };
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let flag = rest[1..].starts_with(['-', '_', '0']);
+        let placeholder = rest[1 + flag as usize..]
+            .chars()
+            .next()
+            .filter(|&specifier| PATH_SPECIFIERS.contains(specifier))
+            .map(|specifier| &rest[..1 + flag as usize + specifier.len_utf8()]);
+        // Combinations chrono rejects, such as `%-%`, stay literal too.
+        let mut formatted = String::new();
+        match placeholder {
+            Some(placeholder) if write!(formatted, "{}", now.format(placeholder)).is_ok() => {
+                expanded.push_str(&formatted);
+                rest = &rest[placeholder.len()..];
+            }
+            _ => {
+                expanded.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+/// Number of threads Whisper uses unless told otherwise: one per available core.
+pub fn default_threads() -> i32 {
+    std::thread::available_parallelism()
+        .map(|threads| threads.get() as i32)
+        .unwrap_or(4)
+}
+
+/// Returns beam search when a beam size is given and greedy decoding keeping
+/// the best of `best_of` candidates otherwise.
+pub fn sampling_strategy(beam_size: Option<i32>, best_of: i32) -> SamplingStrategy {
+    match beam_size {
+        // A negative patience leaves whisper.cpp's default in place.
+        Some(beam_size) => SamplingStrategy::BeamSearch {
+            beam_size,
+            patience: -1.0,
+        },
+        None => SamplingStrategy::Greedy { best_of },
+    }
+}
+
+/// Root mean square level of a block of samples.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Reads every sample of a WAV file as `f32`, normalizing integer PCM to the
+/// range [-1.0, 1.0]. A sample that fails to decode, e.g. at the end of a
+/// truncated file, is reported along with its index.
+pub fn read_wav_samples<R: Read>(reader: hound::WavReader<R>) -> Result<Vec<f32>, anyhow::Error> {
+    let decode_error = |index: usize, err: hound::Error| {
+        anyhow::anyhow!("failed to decode sample {}: {}", index, err)
+    };
+    let spec = reader.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .enumerate()
+            .map(|(index, sample)| sample.map_err(|err| decode_error(index, err)))
+            .collect(),
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .enumerate()
+                .map(|(index, sample)| {
+                    sample
+                        .map(|sample| sample as f32 / scale)
+                        .map_err(|err| decode_error(index, err))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Averages interleaved multi-channel audio into a single mono channel.
+pub fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Resamples mono audio from `from_rate` to `to_rate` using linear interpolation.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    resample_iter(samples.iter().copied(), samples.len(), from_rate, to_rate)
+}
+
+/// Like [`resample`], but reads the `len` input samples one at a time, so
+/// they never have to be in memory together.
+pub fn resample_iter<I>(samples: I, len: usize, from_rate: u32, to_rate: u32) -> Vec<f32>
+where
+    I: Iterator<Item = f32>,
+{
+    let mut samples = samples.take(len);
+    if from_rate == to_rate || len == 0 {
+        return samples.collect();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    let out_len = (len as f64 / ratio).round() as usize;
+
+    // `current` and `next` hold the input samples at `index` and the one
+    // after it, clamped to the last sample.
+    let mut index = 0;
+    let mut current = samples.next().unwrap_or(0.0);
+    let mut next = samples.next().unwrap_or(current);
+    (0..out_len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let target = position.floor() as usize;
+            while index < target && index + 1 < len {
+                index += 1;
+                current = next;
+                next = samples.next().unwrap_or(current);
+            }
+            let fraction = (position - target as f64) as f32;
+            current + (next - current) * fraction
+        })
+        .collect()
+}
+
+/// Linear-interpolation resampler for mono audio that arrives in blocks, such
+/// as the buffers of a capture callback. Unlike [`resample`], it carries its
+/// position and the last input sample over from one block to the next, so
+/// block edges don't click and the output keeps the input's timing however
+/// the blocks are sized. Each output sample is produced once the input sample
+/// after it has arrived.
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    /// Position of the next output sample after `previous`, in units of
+    /// 1/`to_rate` of an input sample. Kept as an integer so it never drifts.
+    position: u64,
+    /// The last input sample, or `None` before the first one arrives.
+    previous: Option<f32>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Resampler {
+        Resampler {
+            from_rate,
+            to_rate,
+            position: 0,
+            previous: None,
+        }
+    }
+
+    /// Resamples the next block of input.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        if self.from_rate == self.to_rate {
+            return samples.to_vec();
+        }
+        let step = self.from_rate as u64;
+        let length = self.to_rate as u64;
+        let mut output =
+            Vec::with_capacity(samples.len() * self.to_rate as usize / step as usize + 1);
+        for &sample in samples {
+            let Some(previous) = self.previous.replace(sample) else {
+                continue;
+            };
+            while self.position < length {
+                let fraction = self.position as f32 / length as f32;
+                output.push(previous + (sample - previous) * fraction);
+                self.position += step;
+            }
+            self.position -= length;
+        }
+        output
+    }
+}
+
+/// Removes the words at the start of `next` that repeat the words at the end of
+/// `previous`, comparing case-insensitively and ignoring punctuation.
+pub fn strip_overlap(previous: &str, next: &str) -> String {
+    fn normalize(word: &str) -> String {
+        word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    let previous: Vec<String> = previous.split_whitespace().map(normalize).collect();
+    let next_words: Vec<&str> = next.split_whitespace().collect();
+    let normalized: Vec<String> = next_words.iter().map(|word| normalize(word)).collect();
+
+    let longest = (1..=previous.len().min(next_words.len()))
+        .rev()
+        .find(|&n| previous[previous.len() - n..] == normalized[..n])
+        .unwrap_or(0);
+
+    next_words[longest..].join(" ")
+}
+
+/// Removes bracketed annotations such as `[Music]`, `(laughs)` or
+/// `[BLANK_AUDIO]` that Whisper emits for non-speech audio.
+pub fn strip_annotations(text: &str) -> String {
+    static ANNOTATION: OnceLock<Regex> = OnceLock::new();
+    let annotation = ANNOTATION.get_or_init(|| Regex::new(r"\s*(\[[^\]]*\]|\([^)]*\))").unwrap());
+    annotation.replace_all(text, "").into_owned()
+}
+
+/// Speech recognition run over one window of live audio. [`Buffer`] goes
+/// through this so that its windowing and voice activity detection can be
+/// exercised without loading a model.
+pub trait WindowTranscriber {
+    /// Returns the segments heard in a window of 16 kHz mono audio, timed
+    /// from the window's first sample.
+    fn transcribe_window(&mut self, window: &[f32]) -> Vec<Segment>;
+}
+
+/// Transcribes windows with a Whisper model.
+pub struct WhisperTranscriber {
+    model: PathBuf,
+    context: Arc<WhisperContext>,
+    options: TranscribeOptions,
+    /// Receives the text decoded so far from the window being transcribed,
+    /// each time Whisper finishes a segment of it.
+    pub partials: Option<Sender<String>>,
+}
+
+impl WhisperTranscriber {
+    /// Loads the model once up front so that every window reuses the same
+    /// context and only pays for a fresh state.
+    pub fn new(
+        model: PathBuf,
+        options: TranscribeOptions,
+        use_gpu: bool,
+        load_timeout: Duration,
+    ) -> Result<WhisperTranscriber, anyhow::Error> {
+        let context = load_model(&model, use_gpu, load_timeout)?;
+
+        Ok(WhisperTranscriber {
+            model,
+            context: Arc::new(context),
+            options,
+            partials: None,
+        })
+    }
+
+    pub fn model(&self) -> &Path {
+        &self.model
+    }
+}
+
+impl WhisperTranscriber {
+    fn run(&self, window: &[f32]) -> Result<Vec<Segment>, anyhow::Error> {
+        let mut state = self.context.create_state()?;
+
+        let mut params = build_params(&self.options);
+        if let Some(partials) = self.partials.clone() {
+            let mut decoded = String::new();
+            params.set_segment_callback_safe(move |data: SegmentCallbackData| {
+                decoded.push_str(&data.text);
+                partials.send(decoded.clone()).ok();
+            });
+        }
+        state.full(params, window)?;
+
+        // Whisper timestamps are in centiseconds.
+        (0..state.full_n_segments()?)
+            .map(|i| {
+                let mut text = state.full_get_segment_text(i)?;
+                if self.options.strip_annotations {
+                    text = strip_annotations(&text);
+                }
+                Ok(Segment {
+                    start_ms: state.full_get_segment_t0(i)? * 10,
+                    end_ms: state.full_get_segment_t1(i)? * 10,
+                    text,
+                    confidence: 0.0,
+                    words: Vec::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl WindowTranscriber for WhisperTranscriber {
+    /// Runs in the capture callback, so a window Whisper fails on is logged
+    /// and skipped rather than taking the session down.
+    fn transcribe_window(&mut self, window: &[f32]) -> Vec<Segment> {
+        match self.run(window) {
+            Ok(segments) => segments,
+            // Ctrl-C cancels the window; there is nothing to emit.
+            Err(_) if self.options.aborted() => Vec::new(),
+            Err(err) => {
+                log::error!("failed to transcribe window: {:#}", err);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Receives the segments a [`Buffer`] transcribes.
+pub trait SegmentSink {
+    /// Takes a segment, timed from the first sample pushed into the buffer.
+    fn emit(&mut self, segment: Segment);
+}
+
+/// Forwards segments to another thread, e.g. the one printing them.
+impl SegmentSink for Sender<Segment> {
+    fn emit(&mut self, segment: Segment) {
+        // Nobody may be listening any more once the session is shutting down.
+        self.send(segment).ok();
+    }
+}
+
+/// Ring buffer of live audio that runs the most recent window through its
+/// transcriber every time half a window of new audio has arrived, so speech
+/// straddling a window boundary is always heard whole at least once.
+pub struct Buffer<T = WhisperTranscriber, S = Sender<Segment>> {
+    pub transcriber: T,
+    /// Receives every transcribed segment.
+    pub sink: S,
+    data: Vec<f32>,
+    /// Index the next sample is written to.
+    pos: usize,
+    /// Number of valid samples in `data`.
+    filled: usize,
+    /// Samples received since the last transcription.
+    pending: usize,
+    /// Samples received in total.
+    received: u64,
+    /// Samples received between two transcriptions of a full window.
+    hop: usize,
+    /// RMS level above which incoming audio counts as speech. Zero disables
+    /// voice activity detection so that every full window is transcribed.
+    pub vad_threshold: f32,
+    /// Number of silent samples following speech after which the window is
+    /// transcribed early.
+    pub vad_silence: usize,
+    speech_detected: bool,
+    silent_samples: usize,
+    /// The last segment sent, timed from the first sample pushed, used to
+    /// drop words that overlapping windows both heard.
+    last_emitted: Option<Segment>,
+}
+
+impl Buffer {
+    /// Buffers `size` samples for a Whisper model loaded from `model`.
+    pub fn new(
+        model: PathBuf,
+        size: usize,
+        options: TranscribeOptions,
+        sender: Sender<Segment>,
+        use_gpu: bool,
+        load_timeout: Duration,
+    ) -> Result<Buffer, anyhow::Error> {
+        let transcriber = WhisperTranscriber::new(model, options, use_gpu, load_timeout)?;
+        Ok(Buffer::with_transcriber(transcriber, size, sender))
+    }
+
+    pub fn model(&self) -> &Path {
+        self.transcriber.model()
+    }
+}
+
+impl<T: WindowTranscriber, S: SegmentSink> Buffer<T, S> {
+    /// Buffers `size` samples, hands each window to `transcriber` and the
+    /// segments it hears to `sink`.
+    pub fn with_transcriber(transcriber: T, size: usize, sink: S) -> Buffer<T, S> {
+        Buffer {
+            transcriber,
+            sink,
+            data: vec![0.0; size],
+            pos: 0,
+            filled: 0,
+            pending: 0,
+            received: 0,
+            hop: (size / 2).max(1),
+            vad_threshold: 0.0,
+            vad_silence: 0,
+            speech_detected: false,
+            silent_samples: 0,
+            last_emitted: None,
+        }
+    }
+
+    /// Pushes a block of samples, tracking its energy so that only windows
+    /// containing speech are transcribed.
+    pub fn push_frame(&mut self, frame: &[f32]) {
+        if rms(frame) >= self.vad_threshold {
+            self.speech_detected = true;
+            self.silent_samples = 0;
+        } else {
+            self.silent_samples += frame.len();
+        }
+
+        for &sample in frame {
+            self.push(sample);
+        }
+
+        if self.vad_threshold > 0.0
+            && self.speech_detected
+            && self.silent_samples >= self.vad_silence
+            && self.pending > 0
+        {
+            self.flush();
+            // The utterance is over, so don't carry it into the next window,
+            // nor dedupe the next utterance against it.
+            self.filled = 0;
+            self.last_emitted = None;
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.data[self.pos] = sample;
+        self.pos = (self.pos + 1) % self.data.len();
+        self.filled = (self.filled + 1).min(self.data.len());
+        self.pending += 1;
+        self.received += 1;
+
+        if self.pending == self.hop {
+            self.flush();
+        }
+    }
+
+    /// How far the buffer is towards its next transcription, from 0.0 right
+    /// after one to 1.0 just before the next.
+    pub fn progress(&self) -> f32 {
+        self.pending as f32 / self.hop as f32
+    }
+
+    /// Transcribes any audio received since the last transcription.
+    pub fn finish(&mut self) {
+        if self.pending > 0 {
+            self.flush();
+        }
+    }
+
+    /// The buffered audio in chronological order, oldest sample first.
+    fn window(&self) -> Vec<f32> {
+        let len = self.data.len();
+        let start = (self.pos + len - self.filled) % len;
+        (0..self.filled)
+            .map(|i| self.data[(start + i) % len])
+            .collect()
+    }
+
+    fn flush(&mut self) {
+        if self.speech_detected {
+            self.transcribe();
+        }
+        self.speech_detected = false;
+        self.pending = 0;
+    }
+
+    fn transcribe(&mut self) {
+        let window_start_ms = ((self.received - self.filled as u64) * 1000 / 16000) as i64;
+        for segment in self.transcriber.transcribe_window(&self.window()) {
+            let segment = Segment {
+                start_ms: window_start_ms + segment.start_ms,
+                end_ms: window_start_ms + segment.end_ms,
+                ..segment
+            };
+            // Only a segment that starts before the last one ended can repeat
+            // it; the same words later on are speech in their own right.
+            let new_text = match &self.last_emitted {
+                Some(last) if segment.start_ms < last.end_ms => {
+                    strip_overlap(&last.text, &segment.text)
+                }
+                _ => segment.text.clone(),
+            };
+            if new_text.is_empty() {
+                continue;
+            }
+            self.last_emitted = Some(segment.clone());
+            self.sink.emit(Segment {
+                text: new_text,
+                ..segment
+            });
+        }
+    }
+}
+
+/// Writes frames of `channels` interleaved samples to the output file. Frames
+/// are written verbatim when the file declares the same number of channels;
+/// otherwise each frame is averaged and that average written to every
+/// channel the file declares, so the data always matches its header.
+pub fn write_input_data<T, U>(input: &[T], writer: &AudioWriterHandle, channels: usize)
+where
+    T: Sample,
+    U: Sample + hound::Sample + FromSample<T> + FromSample<f32>,
+    f32: FromSample<T>,
+    i16: FromSample<U>,
+{
+    if let Ok(mut guard) = writer.try_lock() {
+        if let Some(writer) = guard.as_mut() {
+            let target_channels = writer.channels();
+            if channels == target_channels {
+                for &sample in input.iter() {
+                    let sample: U = U::from_sample(sample);
+                    writer.write_sample(sample);
+                }
+            } else {
+                for frame in input.chunks(channels.max(1)) {
+                    let sum: f32 = frame.iter().map(|&sample| f32::from_sample(sample)).sum();
+                    let sample: U = U::from_sample(sum / frame.len() as f32);
+                    for _ in 0..target_channels {
+                        writer.write_sample(sample);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn initialize_write_stream(
+    device: cpal::Device,
+    writer: AudioWriterHandle,
+    config: cpal::SupportedStreamConfig,
+    silence_monitor: Option<SilenceMonitor>,
+    meter: Option<LevelMeter>,
+    playback: Option<Monitor>,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let channels = config.channels() as usize;
+    let err_fn = move |err| {
+        log::warn!("an error occurred on stream: {}", err);
+    };
+
+    let sample_format = check_sample_format(&config)?;
+    let config: cpal::StreamConfig = config.into();
+    build_with_retry(STREAM_ATTEMPTS, || {
+        let writer = writer.clone();
+        let mut silence_monitor = silence_monitor.clone();
+        let meter = meter.clone();
+        let playback = playback.clone();
+        match sample_format {
+            cpal::SampleFormat::I8 => device.build_input_stream(
+                &config,
+                move |data: &[i8], _: &_| {
+                    if let Some(monitor) = silence_monitor.as_mut() {
+                        monitor.observe(data);
+                    }
+                    if let Some(meter) = &meter {
+                        meter.observe(data);
+                    }
+                    if let Some(playback) = &playback {
+                        playback.push(data);
+                    }
+                    write_input_data::<i8, i8>(data, &writer, channels)
+                },
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &_| {
+                    if let Some(monitor) = silence_monitor.as_mut() {
+                        monitor.observe(data);
+                    }
+                    if let Some(meter) = &meter {
+                        meter.observe(data);
+                    }
+                    if let Some(playback) = &playback {
+                        playback.push(data);
+                    }
+                    write_input_data::<i16, i16>(data, &writer, channels)
+                },
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::I32 => device.build_input_stream(
+                &config,
+                move |data: &[i32], _: &_| {
+                    if let Some(monitor) = silence_monitor.as_mut() {
+                        monitor.observe(data);
+                    }
+                    if let Some(meter) = &meter {
+                        meter.observe(data);
+                    }
+                    if let Some(playback) = &playback {
+                        playback.push(data);
+                    }
+                    write_input_data::<i32, i32>(data, &writer, channels)
+                },
+                err_fn,
+                None,
+            ),
+            // F32; other formats were rejected by check_sample_format.
+            _ => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &_| {
+                    if let Some(monitor) = silence_monitor.as_mut() {
+                        monitor.observe(data);
+                    }
+                    if let Some(meter) = &meter {
+                        meter.observe(data);
+                    }
+                    if let Some(playback) = &playback {
+                        playback.push(data);
+                    }
+                    write_input_data::<f32, f32>(data, &writer, channels)
+                },
+                err_fn,
+                None,
+            ),
+        }
+    })
+}
+
+/// Number of times opening an input stream is attempted before giving up.
+pub const STREAM_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry; it doubles after every failed attempt.
+const STREAM_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Calls `build` up to `attempts` times while it fails with an error that may
+/// go away on its own, such as a device that is still busy or a backend that
+/// hasn't finished switching devices.
+pub fn build_with_retry<F>(attempts: u32, mut build: F) -> Result<cpal::Stream, anyhow::Error>
+where
+    F: FnMut() -> Result<cpal::Stream, cpal::BuildStreamError>,
+{
+    let mut delay = STREAM_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match build() {
+            Ok(stream) => return Ok(stream),
+            Err(
+                err @ (cpal::BuildStreamError::DeviceNotAvailable
+                | cpal::BuildStreamError::BackendSpecific { .. }),
+            ) if attempt < attempts => {
+                log::debug!(
+                    "failed to open input stream ({}); retrying in {} ms",
+                    err,
+                    delay.as_millis()
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "failed to open input stream after {} attempt(s): {}",
+                    attempt,
+                    err
+                ))
+            }
+        }
+    }
+}
+
+/// Returns the sample format of `config` if input streams support it.
+fn check_sample_format(
+    config: &cpal::SupportedStreamConfig,
+) -> Result<cpal::SampleFormat, anyhow::Error> {
+    match config.sample_format() {
+        sample_format @ (cpal::SampleFormat::I8
+        | cpal::SampleFormat::I16
+        | cpal::SampleFormat::I32
+        | cpal::SampleFormat::F32) => Ok(sample_format),
+        sample_format => Err(anyhow::Error::msg(format!(
+            "Unsupported sample format '{sample_format}'"
+        ))),
+    }
+}
+
+/// Opens an output stream on `device` that plays back audio captured with
+/// `input_config`, returning it along with the [`Monitor`] the input callback
+/// feeds.
+pub fn initialize_monitor_stream(
+    device: cpal::Device,
+    input_config: &cpal::SupportedStreamConfig,
+) -> Result<(cpal::Stream, Monitor), anyhow::Error> {
+    let err_fn = move |err| {
+        log::warn!("an error occurred on monitor stream: {}", err);
+    };
+
+    let config = device.default_output_config()?;
+    let channels = config.channels() as usize;
+    let monitor = Monitor {
+        queue: Arc::new(Mutex::new(VecDeque::new())),
+        // A tenth of a second keeps the delay small enough to talk over.
+        capacity: config.sample_rate().0 as usize / 10,
+        input_channels: input_config.channels() as usize,
+        resampler: Arc::new(Mutex::new(Resampler::new(
+            input_config.sample_rate().0,
+            config.sample_rate().0,
+        ))),
+    };
+
+    let playback = monitor.clone();
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::I16 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [i16], _: &_| playback.fill(data, channels),
+            err_fn,
+            None,
+        )?,
+        cpal::SampleFormat::U16 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [u16], _: &_| playback.fill(data, channels),
+            err_fn,
+            None,
+        )?,
+        cpal::SampleFormat::I32 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [i32], _: &_| playback.fill(data, channels),
+            err_fn,
+            None,
+        )?,
+        cpal::SampleFormat::F32 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [f32], _: &_| playback.fill(data, channels),
+            err_fn,
+            None,
+        )?,
+        sample_format => {
+            return Err(anyhow::Error::msg(format!(
+                "Unsupported sample format '{sample_format}'"
+            )))
+        }
+    };
+
+    Ok((stream, monitor))
+}
+
+pub fn initialize_buffered_stream(
+    device: cpal::Device,
+    buffer: Arc<Mutex<Buffer>>,
+    config: cpal::SupportedStreamConfig,
+) -> Result<cpal::Stream, anyhow::Error> {
+    let err_fn = move |err| {
+        log::warn!("an error occurred on stream: {}", err);
+    };
+
+    let sample_rate = config.sample_rate().0;
+    let channels = config.channels() as usize;
+    let sample_format = check_sample_format(&config)?;
+    let config: cpal::StreamConfig = config.into();
+    build_with_retry(STREAM_ATTEMPTS, || {
+        let buffer = buffer.clone();
+        let mut resampler = Resampler::new(sample_rate, 16000);
+        match sample_format {
+            cpal::SampleFormat::I8 => device.build_input_stream(
+                &config,
+                move |data: &[i8], _: &_| push_input_data(data, &buffer, &mut resampler, channels),
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &_| push_input_data(data, &buffer, &mut resampler, channels),
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::I32 => device.build_input_stream(
+                &config,
+                move |data: &[i32], _: &_| push_input_data(data, &buffer, &mut resampler, channels),
+                err_fn,
+                None,
+            ),
+            // F32; other formats were rejected by check_sample_format.
+            _ => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &_| push_input_data(data, &buffer, &mut resampler, channels),
+                err_fn,
+                None,
+            ),
+        }
+    })
+}
+
+/// Converts captured samples to normalized mono `f32` at 16 kHz, the input
+/// Whisper expects, and pushes them into the buffer.
+fn push_input_data<T>(
+    input: &[T],
+    buffer: &Mutex<Buffer>,
+    resampler: &mut Resampler,
+    channels: usize,
+) where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    let mut frame: Vec<f32> = input
+        .iter()
+        .map(|&sample| f32::from_sample(sample))
+        .collect();
+    if channels > 1 {
+        frame = downmix(&frame, channels);
+    }
+    let frame = resampler.process(&frame);
+    buffer.lock().unwrap().push_frame(&frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records every segment emitted.
+    impl SegmentSink for Vec<Segment> {
+        fn emit(&mut self, segment: Segment) {
+            self.push(segment);
+        }
+    }
+
+    /// Records the length of every window and answers each with the next of
+    /// `replies`, as one segment spanning the window.
+    struct FakeTranscriber {
+        windows: Vec<usize>,
+        replies: &'static [&'static str],
+    }
+
+    impl WindowTranscriber for FakeTranscriber {
+        fn transcribe_window(&mut self, window: &[f32]) -> Vec<Segment> {
+            let text = self.replies[self.windows.len() % self.replies.len()].to_string();
+            self.windows.push(window.len());
+            vec![Segment {
+                start_ms: 0,
+                end_ms: (window.len() * 1000 / 16000) as i64,
+                text,
+                confidence: 0.0,
+                words: Vec::new(),
+            }]
+        }
+    }
+
+    fn buffer(size: usize) -> Buffer<FakeTranscriber, Vec<Segment>> {
+        buffer_replying(size, &["alpha", "bravo", "charlie", "delta"])
+    }
+
+    fn buffer_replying(
+        size: usize,
+        replies: &'static [&'static str],
+    ) -> Buffer<FakeTranscriber, Vec<Segment>> {
+        let transcriber = FakeTranscriber {
+            windows: Vec::new(),
+            replies,
+        };
+        Buffer::with_transcriber(transcriber, size, Vec::new())
+    }
+
+    fn texts(buffer: &Buffer<FakeTranscriber, Vec<Segment>>) -> Vec<&str> {
+        buffer
+            .sink
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect()
+    }
+
+    fn expand(template: &str) -> PathBuf {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 3, 7)
+            .unwrap()
+            .and_hms_opt(9, 5, 30)
+            .unwrap()
+            .and_local_timezone(chrono::Local)
+            .unwrap();
+        expand_path_template(Path::new(template), &now)
+    }
+
+    #[test]
+    fn expand_path_template_replaces_placeholders() {
+        assert_eq!(
+            expand("rec-%Y%m%d-%H%M%S.wav"),
+            PathBuf::from("rec-20240307-090530.wav")
+        );
+        assert_eq!(expand("%-d.%-m %%.wav"), PathBuf::from("7.3 %.wav"));
+    }
+
+    #[test]
+    fn expand_path_template_keeps_literal_percent_signs() {
+        assert_eq!(expand("rec-100%.wav"), PathBuf::from("rec-100%.wav"));
+        assert_eq!(expand("50% off.wav"), PathBuf::from("50% off.wav"));
+        assert_eq!(expand("100%-%Y.wav"), PathBuf::from("100%-2024.wav"));
+        assert_eq!(expand("take%"), PathBuf::from("take%"));
+    }
+
+    /// One second of a 440 Hz tone at `rate`.
+    fn tone(rate: u32) -> Vec<f32> {
+        (0..rate)
+            .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn resampler_output_does_not_depend_on_block_size() {
+        let input = tone(44100);
+        let whole = Resampler::new(44100, 16000).process(&input);
+        for block in [512, 1024, 441, 1] {
+            let mut resampler = Resampler::new(44100, 16000);
+            let blocks: Vec<f32> = input
+                .chunks(block)
+                .flat_map(|chunk| resampler.process(chunk))
+                .collect();
+            assert_eq!(blocks, whole, "block size {}", block);
+        }
+        // One second in, one second out.
+        assert_eq!(whole.len(), 16000);
+    }
+
+    #[test]
+    fn resampler_matches_whole_buffer_resampling() {
+        let input = tone(44100);
+        let expected = resample(&input, 44100, 16000);
+        let mut resampler = Resampler::new(44100, 16000);
+        let streamed: Vec<f32> = input
+            .chunks(1024)
+            .flat_map(|chunk| resampler.process(chunk))
+            .collect();
+        for (index, (streamed, expected)) in streamed.iter().zip(&expected).enumerate() {
+            assert!((streamed - expected).abs() < 1e-4, "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn buffer_transcribes_every_half_window_without_vad() {
+        let mut buffer = buffer(32000);
+        for _ in 0..30 {
+            buffer.push_frame(&[0.0; 1600]);
+        }
+
+        assert_eq!(buffer.transcriber.windows, vec![16000, 32000, 32000]);
+        let times: Vec<(i64, i64)> = buffer
+            .sink
+            .iter()
+            .map(|segment| (segment.start_ms, segment.end_ms))
+            .collect();
+        assert_eq!(times, vec![(0, 1000), (0, 2000), (1000, 3000)]);
+    }
+
+    #[test]
+    fn buffer_skips_silence_and_flushes_after_an_utterance() {
+        let mut buffer = buffer(32000);
+        buffer.vad_threshold = 0.1;
+        buffer.vad_silence = 3200;
+        for _ in 0..20 {
+            buffer.push_frame(&[0.0; 1600]);
+        }
+        assert!(buffer.transcriber.windows.is_empty());
+
+        for _ in 0..5 {
+            buffer.push_frame(&[0.5; 1600]);
+        }
+        for _ in 0..2 {
+            buffer.push_frame(&[0.0; 1600]);
+        }
+        // The silence before the speech kept the ring full, so the early
+        // flush sees a whole window.
+        assert_eq!(buffer.transcriber.windows, vec![32000]);
+        assert_eq!(buffer.sink.len(), 1);
+
+        // The utterance was flushed, so the next one starts a fresh window.
+        for _ in 0..3 {
+            buffer.push_frame(&[0.5; 1600]);
+        }
+        buffer.finish();
+        assert_eq!(buffer.transcriber.windows, vec![32000, 4800]);
+    }
+
+    #[test]
+    fn buffer_drops_words_overlapping_windows_both_heard() {
+        let mut buffer = buffer_replying(32000, &["one two three", "three four"]);
+        for _ in 0..20 {
+            buffer.push_frame(&[0.5; 1600]);
+        }
+
+        assert_eq!(texts(&buffer), vec!["one two three", "four"]);
+    }
+
+    #[test]
+    fn buffer_keeps_repeated_words_in_a_new_utterance() {
+        let mut buffer = buffer_replying(32000, &["She said no.", "No way."]);
+        buffer.vad_threshold = 0.1;
+        buffer.vad_silence = 3200;
+        for _ in 0..5 {
+            buffer.push_frame(&[0.5; 1600]);
+        }
+        for _ in 0..2 {
+            buffer.push_frame(&[0.0; 1600]);
+        }
+        for _ in 0..5 {
+            buffer.push_frame(&[0.5; 1600]);
+        }
+        buffer.finish();
+
+        assert_eq!(texts(&buffer), vec!["She said no.", "No way."]);
+    }
+
+    #[test]
+    fn buffer_finish_transcribes_pending_audio() {
+        let mut buffer = buffer(32000);
+        buffer.push_frame(&[0.5; 1600]);
+        buffer.finish();
+        buffer.finish();
+
+        assert_eq!(buffer.transcriber.windows, vec![1600]);
+        assert_eq!(buffer.sink.len(), 1);
+    }
+}